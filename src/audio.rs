@@ -0,0 +1,62 @@
+use crate::tone::{AudioPattern, ToneGenerator, ToneSettings};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// Receives the PCM samples (mono, -1.0 to 1.0) the emulator produces every frame
+pub trait AudioSink {
+    fn push_samples(&mut self, samples: &[f32]);
+}
+
+// Renders one frame of buzzer sound at a time and hands it to every sink
+pub struct AudioOutput {
+    sample_rate: u32,
+    tone: ToneGenerator,
+    sinks: Vec<Box<dyn AudioSink>>,
+    samples: Vec<f32>,
+}
+
+impl AudioOutput {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> Self {
+        AudioOutput {
+            sample_rate,
+            tone: ToneGenerator::new(settings, sample_rate),
+            sinks: Vec::new(),
+            // the timers run at 60 Hz, so a frame is exactly one timer tick
+            samples: vec![0.0; (sample_rate / 60) as usize],
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.tone.set_sound_timer(value);
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.tone.set_pattern(pattern);
+    }
+
+    // Silences the buzzer after the machine was reset
+    pub fn reset(&mut self) {
+        self.tone.set_sound_timer(0);
+        self.tone.set_pattern(None);
+    }
+
+    // The samples of the last rendered frame
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn render_frame(&mut self) {
+        self.tone.fill(&mut self.samples);
+        for sink in self.sinks.iter_mut() {
+            sink.push_samples(&self.samples);
+        }
+    }
+}
@@ -1,56 +1,68 @@
 extern crate sdl2;
-use sdl2::{Sdl, audio::{AudioSpecDesired, AudioCallback, AudioDevice}};
+use sdl2::{Sdl, audio::{AudioSpecDesired, AudioQueue}};
 
+use crate::audio::{AudioSink, SAMPLE_RATE};
 
+// Frames of sound queued ahead of playback, more only adds latency
+const MAX_QUEUED_FRAMES: u32 = 4;
+
+// Plays the samples the emulator renders every frame
 pub struct AudioDriver {
-    device: AudioDevice<SquareWave>
+    queue: AudioQueue<f32>,
+    max_queued_bytes: u32,
 }
 
 impl AudioDriver {
     pub fn new(sdl_context: &Sdl) -> AudioDriver {
         let audio_subsystem = sdl_context.audio().unwrap();
         let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
+            freq: Some(SAMPLE_RATE as i32),
             channels: Some(1),
             samples: None,
         };
 
-        let device = audio_subsystem
-            .open_playback(None, &desired_spec, |spec| {
-                SquareWave {
-                    phase_inc: 240.0 / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.25
-                }
-            }).unwrap();
-
-        device.resume();
-        AudioDriver { device }
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &desired_spec).unwrap();
+        let frame_bytes = queue.spec().freq as u32 / 60 * std::mem::size_of::<f32>() as u32;
+        queue.resume();
+        AudioDriver { queue, max_queued_bytes: frame_bytes * MAX_QUEUED_FRAMES }
     }
 
-    pub fn beep(&self, should_beep: bool) {
-        if should_beep {
-            self.device.resume();
-        } else {
-            self.device.pause();
-        }
+    // The rate the device actually plays at, samples must be rendered for it
+    pub fn sample_rate(&self) -> u32 {
+        self.queue.spec().freq as u32
     }
 }
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32
+impl AudioSink for AudioDriver {
+    fn push_samples(&mut self, samples: &[f32]) {
+        let samples = samples_to_queue(self.queue.size(), self.max_queued_bytes, samples);
+        if !samples.is_empty() {
+            let _ = self.queue.queue_audio(samples);
+        }
+    }
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
+// The emulator runs slightly faster than the sound card now and then. Rather
+// than lag behind the picture, a frame arriving while the queue is full is left
+// out; what is already queued keeps playing so the sound doesn't click.
+fn samples_to_queue(queued_bytes: u32, max_queued_bytes: u32, samples: &[f32]) -> &[f32] {
+    if queued_bytes > max_queued_bytes {
+        return &[];
+    }
+    samples
+}
 
-    fn callback(&mut self, out: &mut [Self::Channel]) {
-        // generate square wave
-        for x in out.iter_mut() {
-            *x = self.volume * if self.phase < 0.5 { 1.0 } else { -1.0 };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
+#[test]
+fn test_samples_to_queue() {
+    let frame = [0.5; 735];
+    let frame_bytes = (frame.len() * std::mem::size_of::<f32>()) as u32;
+    let max_queued_bytes = frame_bytes * MAX_QUEUED_FRAMES;
+    // a queue nothing plays from, so it only ever grows
+    let mut queue: Vec<f32> = Vec::new();
+    for _ in 0..MAX_QUEUED_FRAMES * 3 {
+        let queued_bytes = (queue.len() * std::mem::size_of::<f32>()) as u32;
+        queue.extend_from_slice(samples_to_queue(queued_bytes, max_queued_bytes, &frame));
     }
+    // everything queued up to the limit stayed, only the frames after it were left out
+    assert_eq!(queue.len(), frame.len() * (MAX_QUEUED_FRAMES as usize + 1));
 }
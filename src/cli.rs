@@ -0,0 +1,526 @@
+use crate::display_filter::{Filter, DEFAULT_BLEND_FRAMES, DEFAULT_DECAY};
+use crate::font::Font;
+use crate::input_driver::DEFAULT_DEADZONE;
+use crate::memory::{UninitializedCheck, DEFAULT_LOAD_ADDRESS};
+use crate::orientation::{Orientation, Rotation};
+use crate::palette::Palette;
+use crate::platform::{Platform, Quirks, StackDepth, PLATFORMS};
+use crate::tone::{ToneSettings, Waveform};
+use crate::tty_frontend::TtyStyle;
+use crate::INSTRUCTIONS_PER_FRAME;
+
+const DEFAULT_SCALE: u32 = 15;
+
+pub const USAGE: &str = "\
+usage: chip_8_rs [options] <rom>
+
+emulation:
+  --ipf <n>              instructions per frame (default 10)
+  --hz <n>               instructions per second, rounded to whole frames
+  --platform <name>      chip8, schip or xochip (default chip8)
+  --quirk <name>         enable a quirk: shift, memory, jump, vfreset, clip
+  --no-quirk <name>      disable a quirk
+  --seed <n>             seed for the random number generator
+  --load-address <addr>  where the ROM is loaded and started (default 0x200)
+  --entry <addr>         where execution starts (default: the load address)
+  --segment <file@addr>  load another binary at the given address, can be repeated
+  --memory <bytes>       memory size, up to 65536 on xochip (default: all the platform has)
+  --font <name|file>     schip, vip, dream6800, eti660, fishnchips or a font file
+  --font-address <addr>  where the font is in memory (default 0x000)
+  --stack-depth <n>      nested calls before the stack overflows, or unlimited
+                         (default 12 on chip8, 16 on schip and xochip)
+
+display:
+  --scale <n>            window scale (default 15)
+  --integer-scale        scale by whole multiples only
+  --fullscreen           start in fullscreen
+  --palette <palette>    green, white, amber, lcd or RRGGBB,RRGGBB
+  --filter <name>        persistence, blend, scanlines or grid
+  --decay <x>            brightness kept per frame by persistence (0-1)
+  --blend-frames <n>     frames averaged by blend
+  --rotate <degrees>     0, 90, 180 or 270
+  --flip <h|v|hv>        mirror the picture
+  --tty                  draw in the terminal with half blocks
+  --braille              draw in the terminal with braille dots
+
+sound:
+  --tone <hz>            buzzer frequency (default 440)
+  --volume <x>           buzzer volume (0-1)
+  --waveform <name>      square, sine, triangle or noise
+  --wav <file>           record the sound to a WAV file
+
+input:
+  --keymap <name|file>   qwerty, qwertz, azerty, dvorak, numpad or a binding file
+  --deadzone <n>         controller stick deadzone (0-32767)
+
+debugging:
+  --debug                disassemble every instruction
+  --trace                print the machine state before every instruction
+  --strict               stop at writes below 0x200, reads and jumps past the
+                         program, running written data and I past the end of memory
+  --uninitialized <mode> off, warn or break on reads of memory and registers
+                         the program never set (default off)
+  --symbols <file>       names for addresses in backtraces (default: the ROM's
+                         .sym file if there is one)
+  --headless             run without window, sound or input
+  --frames <n>           stop after n frames
+  --config <file>        settings file (default ~/.config/chip_8_rs/config.toml)
+  --database <file>      program database (default ~/.config/chip_8_rs/programs.json)
+  --help                 show this help
+";
+
+#[derive(Debug)]
+pub struct Options {
+    pub rom: Option<String>,
+    pub config: Option<String>,
+    pub database: Option<String>,
+    // title of the program as found in the database
+    pub title: Option<String>,
+    // physical inputs added to the keymap for this ROM
+    pub bindings: Vec<(String, usize)>,
+    pub instructions_per_frame: u32,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: Option<u64>,
+    pub load_address: u16,
+    // where execution starts, the load address when not given
+    pub entry: Option<u16>,
+    // extra binaries loaded after the ROM, with their addresses
+    pub segments: Vec<(String, u16)>,
+    // memory size in bytes, the platform's full memory when not given
+    pub memory_size: Option<usize>,
+    // the glyphs FX29/FX30 use and where they are loaded
+    pub font: Font,
+    // the platform's when not given
+    pub stack_depth: Option<StackDepth>,
+    pub palette: Palette,
+    pub debug: bool,
+    pub trace: bool,
+    pub strict: bool,
+    pub uninitialized: UninitializedCheck,
+    pub symbols: Option<String>,
+    pub help: bool,
+    pub scale: u32,
+    pub integer_scale: bool,
+    pub fullscreen: bool,
+    pub filters: Vec<Filter>,
+    pub decay: f32,
+    pub blend_frames: usize,
+    pub orientation: Orientation,
+    pub tty: Option<TtyStyle>,
+    pub tone: ToneSettings,
+    pub wav: Option<String>,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub keymap: Option<String>,
+    pub deadzone: i16,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            rom: None,
+            config: None,
+            database: None,
+            title: None,
+            bindings: Vec::new(),
+            instructions_per_frame: INSTRUCTIONS_PER_FRAME,
+            platform: Platform::default(),
+            quirks: Quirks::default(),
+            seed: None,
+            load_address: DEFAULT_LOAD_ADDRESS,
+            entry: None,
+            segments: Vec::new(),
+            memory_size: None,
+            font: Font::default(),
+            stack_depth: None,
+            palette: Palette::default(),
+            debug: false,
+            trace: false,
+            strict: false,
+            uninitialized: UninitializedCheck::Off,
+            symbols: None,
+            help: false,
+            scale: DEFAULT_SCALE,
+            integer_scale: false,
+            fullscreen: false,
+            filters: Vec::new(),
+            decay: DEFAULT_DECAY,
+            blend_frames: DEFAULT_BLEND_FRAMES,
+            orientation: Orientation::default(),
+            tty: None,
+            tone: ToneSettings::default(),
+            wav: None,
+            headless: false,
+            frames: None,
+            keymap: None,
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    parse_args_over(Options::default(), args)
+}
+
+// Applies the command line on top of options that came from elsewhere, e.g. a config file
+pub fn parse_args_over(mut options: Options, args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    // quirks are applied on top of the platform's, whatever the order of the options
+    let mut quirks = Vec::new();
+    let mut platform_given = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => options.help = true,
+            "--ipf" => {
+                let value = next_value(&mut args, "--ipf")?;
+                options.instructions_per_frame = match value.parse() {
+                    Ok(ipf) if ipf > 0 => ipf,
+                    _ => return Err(format!("invalid instructions per frame: {}", value)),
+                };
+            }
+            "--hz" => {
+                let value = next_value(&mut args, "--hz")?;
+                options.instructions_per_frame = match value.parse::<u32>() {
+                    Ok(hz) if hz > 0 => (hz.saturating_add(30) / 60).max(1),
+                    _ => return Err(format!("invalid instruction rate: {}", value)),
+                };
+            }
+            "--platform" => {
+                let value = next_value(&mut args, "--platform")?;
+                options.platform = Platform::from_name(value)
+                    .ok_or(format!("unknown platform {}, expected one of {}", value, PLATFORMS.join(", ")))?;
+                platform_given = true;
+            }
+            "--config" => options.config = Some(next_value(&mut args, "--config")?.to_string()),
+            "--database" => options.database = Some(next_value(&mut args, "--database")?.to_string()),
+            "--quirk" => quirks.push((next_value(&mut args, "--quirk")?, true)),
+            "--no-quirk" => quirks.push((next_value(&mut args, "--no-quirk")?, false)),
+            "--seed" => {
+                let value = next_value(&mut args, "--seed")?;
+                options.seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+            }
+            "--load-address" => {
+                let value = next_value(&mut args, "--load-address")?;
+                options.load_address = parse_address(value)?;
+            }
+            "--entry" => {
+                let value = next_value(&mut args, "--entry")?;
+                options.entry = Some(parse_address(value)?);
+            }
+            "--segment" => {
+                let value = next_value(&mut args, "--segment")?;
+                options.segments.push(parse_segment(value)?);
+            }
+            "--memory" => {
+                let value = next_value(&mut args, "--memory")?;
+                options.memory_size = Some(value.parse().map_err(|_| format!("invalid memory size: {}", value))?);
+            }
+            "--font" => options.font = with_font(&options.font, next_value(&mut args, "--font")?)?,
+            "--font-address" => {
+                let value = next_value(&mut args, "--font-address")?;
+                options.font.address = parse_address(value)?;
+            }
+            "--stack-depth" => options.stack_depth = Some(parse_stack_depth(next_value(&mut args, "--stack-depth")?)?),
+            "--palette" => options.palette = Palette::from_spec(next_value(&mut args, "--palette")?)?,
+            "--debug" => options.debug = true,
+            "--trace" => options.trace = true,
+            "--strict" => options.strict = true,
+            "--uninitialized" => options.uninitialized = parse_uninitialized(next_value(&mut args, "--uninitialized")?)?,
+            "--symbols" => options.symbols = Some(next_value(&mut args, "--symbols")?.to_string()),
+            "--scale" => {
+                let value = next_value(&mut args, "--scale")?;
+                options.scale = match value.parse() {
+                    Ok(scale) if scale > 0 => scale,
+                    _ => return Err(format!("invalid scale: {}", value)),
+                };
+            }
+            "--integer-scale" => options.integer_scale = true,
+            "--fullscreen" => options.fullscreen = true,
+            "--filter" => {
+                let value = next_value(&mut args, "--filter")?;
+                let filter = Filter::from_name(value).ok_or(format!("unknown filter: {}", value))?;
+                options.filters.push(filter);
+            }
+            "--decay" => {
+                let value = next_value(&mut args, "--decay")?;
+                options.decay = match value.parse() {
+                    Ok(decay) if (0.0..1.0).contains(&decay) => decay,
+                    _ => return Err(format!("invalid decay: {}", value)),
+                };
+            }
+            "--blend-frames" => {
+                let value = next_value(&mut args, "--blend-frames")?;
+                options.blend_frames = match value.parse() {
+                    Ok(frames) if frames > 0 => frames,
+                    _ => return Err(format!("invalid frame count: {}", value)),
+                };
+            }
+            "--rotate" => {
+                let value = next_value(&mut args, "--rotate")?;
+                options.orientation.rotation = value.parse().ok()
+                    .and_then(Rotation::from_degrees)
+                    .ok_or(format!("invalid rotation: {} (expected 0, 90, 180 or 270)", value))?;
+            }
+            "--flip" => {
+                let value = next_value(&mut args, "--flip")?;
+                options.orientation.set_flip(value)?;
+            }
+            "--tty" => options.tty = options.tty.or(Some(TtyStyle::HalfBlocks)),
+            "--braille" => options.tty = Some(TtyStyle::Braille),
+            "--tone" => {
+                let value = next_value(&mut args, "--tone")?;
+                options.tone.frequency = match value.parse() {
+                    Ok(frequency) if frequency > 0.0 => frequency,
+                    _ => return Err(format!("invalid tone frequency: {}", value)),
+                };
+            }
+            "--volume" => {
+                let value = next_value(&mut args, "--volume")?;
+                options.tone.volume = match value.parse() {
+                    Ok(volume) if (0.0..=1.0).contains(&volume) => volume,
+                    _ => return Err(format!("invalid volume: {}", value)),
+                };
+            }
+            "--waveform" => {
+                let value = next_value(&mut args, "--waveform")?;
+                options.tone.waveform = Waveform::from_name(value).ok_or(format!("unknown waveform: {}", value))?;
+            }
+            "--wav" => options.wav = Some(next_value(&mut args, "--wav")?.to_string()),
+            "--headless" => options.headless = true,
+            "--frames" => {
+                let value = next_value(&mut args, "--frames")?;
+                options.frames = Some(value.parse().map_err(|_| format!("invalid frame count: {}", value))?);
+            }
+            "--keymap" => options.keymap = Some(next_value(&mut args, "--keymap")?.to_string()),
+            "--deadzone" => {
+                let value = next_value(&mut args, "--deadzone")?;
+                options.deadzone = match value.parse() {
+                    Ok(deadzone) if deadzone >= 0 => deadzone,
+                    _ => return Err(format!("invalid deadzone: {} (expected 0-32767)", value)),
+                };
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ if options.rom.is_some() => return Err(format!("more than one ROM given: {}", arg)),
+            _ => options.rom = Some(arg.clone()),
+        }
+    }
+
+    if platform_given {
+        options.quirks = options.platform.quirks();
+    }
+    for (name, enabled) in quirks {
+        options.quirks.set(name, enabled)?;
+    }
+    Ok(options)
+}
+
+// Addresses are hexadecimal with 0x in front, decimal without
+fn parse_address(value: &str) -> Result<u16, String> {
+    let address = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => value.parse(),
+    };
+    address.map_err(|_| format!("invalid address: {} (expected 0x0000-0xFFFF or 0-65535)", value))
+}
+
+// off, warn or break
+pub fn parse_uninitialized(value: &str) -> Result<UninitializedCheck, String> {
+    UninitializedCheck::from_name(value).ok_or(format!("unknown uninitialized mode {}, expected off, warn or break", value))
+}
+
+// a number of calls or unlimited
+pub fn parse_stack_depth(value: &str) -> Result<StackDepth, String> {
+    StackDepth::from_name(value).ok_or(format!("invalid stack depth: {} (expected a number or unlimited)", value))
+}
+
+// The named font (or font file) at the address the current font is at
+pub fn with_font(current: &Font, name: &str) -> Result<Font, String> {
+    Ok(Font { address: current.address, ..Font::from_name_or_file(name)? })
+}
+
+// A binary and where it goes, e.g. "monitor.bin@0x000"
+pub fn parse_segment(value: &str) -> Result<(String, u16), String> {
+    match value.rsplit_once('@') {
+        Some((file, address)) if !file.is_empty() => Ok((file.to_string(), parse_address(address)?)),
+        _ => Err(format!("invalid segment: {} (expected <file>@<address>)", value)),
+    }
+}
+
+fn next_value<'a>(args: &mut std::slice::Iter<'a, String>, option: &str) -> Result<&'a str, String> {
+    args.next().map(|value| value.as_str()).ok_or(format!("{} expects a value", option))
+}
+
+#[cfg(test)]
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_parse_defaults() {
+    let options = parse_args(&args(&[])).unwrap();
+    assert_eq!(options.rom, None);
+    assert_eq!(options.instructions_per_frame, INSTRUCTIONS_PER_FRAME);
+    assert_eq!(options.quirks, Platform::Chip8.quirks());
+    assert_eq!(options.scale, DEFAULT_SCALE);
+    assert!(!options.integer_scale);
+}
+
+#[test]
+fn test_parse_scale() {
+    let options = parse_args(&args(&["--scale", "4", "--integer-scale", "game.ch8"])).unwrap();
+    assert_eq!(options.rom.as_deref(), Some("game.ch8"));
+    assert_eq!(options.scale, 4);
+    assert!(options.integer_scale);
+
+    assert!(parse_args(&args(&["--scale", "0"])).is_err());
+    assert!(parse_args(&args(&["--scale"])).is_err());
+}
+
+#[test]
+fn test_parse_filters() {
+    let options = parse_args(&args(&["--filter", "scanlines", "--filter", "persistence", "--decay", "0.8"])).unwrap();
+    assert_eq!(options.filters, vec![Filter::Scanlines, Filter::Persistence]);
+    assert_eq!(options.decay, 0.8);
+
+    assert!(parse_args(&args(&["--filter", "bloom"])).is_err());
+    assert!(parse_args(&args(&["--decay", "1.5"])).is_err());
+}
+
+#[test]
+fn test_parse_orientation() {
+    let options = parse_args(&args(&["--rotate", "90", "--flip", "h"])).unwrap();
+    assert_eq!(options.orientation.rotation, Rotation::Clockwise90);
+    assert!(options.orientation.flip_horizontal);
+
+    assert!(parse_args(&args(&["--rotate", "45"])).is_err());
+    assert!(parse_args(&args(&["--flip", "x"])).is_err());
+}
+
+#[test]
+fn test_parse_tty() {
+    assert_eq!(parse_args(&args(&[])).unwrap().tty, None);
+    assert_eq!(parse_args(&args(&["--tty"])).unwrap().tty, Some(TtyStyle::HalfBlocks));
+    assert_eq!(parse_args(&args(&["--braille", "--tty"])).unwrap().tty, Some(TtyStyle::Braille));
+}
+
+#[test]
+fn test_parse_tone() {
+    let options = parse_args(&args(&["--tone", "880", "--volume", "0.5", "--waveform", "sine"])).unwrap();
+    assert_eq!(options.tone.frequency, 880.0);
+    assert_eq!(options.tone.volume, 0.5);
+    assert_eq!(options.tone.waveform, Waveform::Sine);
+
+    assert!(parse_args(&args(&["--volume", "2"])).is_err());
+    assert!(parse_args(&args(&["--waveform", "saw"])).is_err());
+}
+
+#[test]
+fn test_parse_headless() {
+    let options = parse_args(&args(&["--headless", "--frames", "120", "--wav", "out.wav"])).unwrap();
+    assert!(options.headless);
+    assert_eq!(options.frames, Some(120));
+    assert_eq!(options.wav.as_deref(), Some("out.wav"));
+
+    assert!(parse_args(&args(&["--frames", "-1"])).is_err());
+}
+
+#[test]
+fn test_parse_deadzone() {
+    assert_eq!(parse_args(&args(&[])).unwrap().deadzone, DEFAULT_DEADZONE);
+    assert_eq!(parse_args(&args(&["--deadzone", "12000"])).unwrap().deadzone, 12000);
+    assert!(parse_args(&args(&["--deadzone", "-5"])).is_err());
+    assert!(parse_args(&args(&["--deadzone", "40000"])).is_err());
+}
+
+#[test]
+fn test_parse_emulation() {
+    let options = parse_args(&args(&["--hz", "700", "--seed", "42", "--load-address", "0x600", "--debug", "game.ch8"])).unwrap();
+    assert_eq!(options.instructions_per_frame, 12);
+    assert_eq!(options.seed, Some(42));
+    assert_eq!(options.load_address, 0x600);
+    assert!(options.debug);
+    assert_eq!(parse_args(&args(&["--ipf", "30"])).unwrap().instructions_per_frame, 30);
+
+    assert!(parse_args(&args(&["--ipf", "0"])).is_err());
+    assert_eq!(parse_args(&args(&["--hz", "4294967295"])).unwrap().instructions_per_frame, u32::MAX / 60);
+    assert!(parse_args(&args(&["--load-address", "0x10000"])).is_err());
+    assert_eq!(parse_args(&args(&["--load-address", "512"])).unwrap().load_address, 0x200);
+    assert!(parse_args(&args(&["--load-address", "2A0"])).is_err());
+    assert!(parse_args(&args(&["a.ch8", "b.ch8"])).is_err());
+    assert!(parse_args(&args(&["--help"])).unwrap().help);
+}
+
+#[test]
+fn test_parse_platform() {
+    // quirks apply on top of the platform regardless of their order
+    let options = parse_args(&args(&["--no-quirk", "clip", "--platform", "schip", "--quirk", "memory"])).unwrap();
+    assert_eq!(options.platform, Platform::SuperChip);
+    assert!(options.quirks.jump_vx);
+    assert!(options.quirks.memory_increment);
+    assert!(!options.quirks.clip_sprites);
+
+    assert!(parse_args(&args(&["--platform", "megachip"])).is_err());
+    assert!(parse_args(&args(&["--quirk", "wobble"])).is_err());
+}
+
+#[test]
+fn test_parse_palette() {
+    assert_eq!(parse_args(&args(&[])).unwrap().palette, Palette::default());
+    assert_eq!(parse_args(&args(&["--palette", "amber"])).unwrap().palette, Palette::preset("amber").unwrap());
+    assert!(parse_args(&args(&["--palette", "pink"])).is_err());
+}
+
+#[test]
+fn test_parse_args_over() {
+    let mut base = Options { instructions_per_frame: 30, ..Options::default() };
+    base.quirks.clip_sprites = false;
+    let options = parse_args_over(base, &args(&["--scale", "4"])).unwrap();
+    assert_eq!(options.instructions_per_frame, 30);
+    assert!(!options.quirks.clip_sprites);
+
+    // a platform on the command line replaces all quirks
+    let mut base = Options::default();
+    base.quirks.clip_sprites = false;
+    let options = parse_args_over(base, &args(&["--platform", "chip8"])).unwrap();
+    assert!(options.quirks.clip_sprites);
+}
+
+#[test]
+fn test_parse_segments() {
+    let options = parse_args(&args(&["--entry", "0x000", "--segment", "font.bin@0", "--segment", "a@b.bin@0x300"])).unwrap();
+    assert_eq!(options.entry, Some(0));
+    assert_eq!(options.segments, vec![("font.bin".to_string(), 0), ("a@b.bin".to_string(), 0x300)]);
+
+    assert!(parse_args(&args(&["--segment", "data.bin"])).is_err());
+    assert!(parse_args(&args(&["--segment", "@0x300"])).is_err());
+    assert!(parse_args(&args(&["--segment", "data.bin@zz"])).is_err());
+}
+
+#[test]
+fn test_parse_font() {
+    let options = parse_args(&args(&["--font-address", "0x050", "--font", "vip"])).unwrap();
+    assert_eq!(options.font, Font { address: 0x50, ..Font::preset("vip").unwrap() });
+    assert_eq!(parse_args(&args(&[])).unwrap().font, Font::default());
+    assert!(parse_args(&args(&["--font", "no-such-font"])).is_err());
+}
+
+#[test]
+fn test_parse_memory_checks() {
+    let options = parse_args(&args(&["--strict", "--uninitialized", "warn"])).unwrap();
+    assert!(options.strict);
+    assert_eq!(options.uninitialized, UninitializedCheck::Warn);
+    assert_eq!(parse_args(&args(&[])).unwrap().uninitialized, UninitializedCheck::Off);
+    assert!(parse_args(&args(&["--uninitialized", "loud"])).is_err());
+}
+
+#[test]
+fn test_parse_call_stack() {
+    let options = parse_args(&args(&["--stack-depth", "unlimited", "--symbols", "pong.sym"])).unwrap();
+    assert_eq!(options.stack_depth, Some(StackDepth::Unlimited));
+    assert_eq!(options.symbols.as_deref(), Some("pong.sym"));
+    assert_eq!(parse_args(&args(&["--stack-depth", "24"])).unwrap().stack_depth, Some(StackDepth::Frames(24)));
+    assert_eq!(parse_args(&args(&[])).unwrap().stack_depth, None);
+    assert!(parse_args(&args(&["--stack-depth", "0"])).is_err());
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::cli::{parse_segment, parse_stack_depth, parse_uninitialized, with_font, Options};
+use crate::database::Database;
+use crate::orientation::Rotation;
+use crate::palette::Palette;
+use crate::platform::{Platform, PLATFORMS};
+
+// Settings a config file can give, globally or for a single ROM. Anything left
+// out keeps the value of the layer below.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub platform: Option<String>,
+    pub quirks: BTreeMap<String, bool>,
+    pub ipf: Option<u32>,
+    pub palette: Option<String>,
+    pub keymap: Option<String>,
+    pub rotation: Option<u32>,
+    // horizontal, vertical, both or none, or h, v and hv like --flip
+    pub flip: Option<String>,
+    pub scale: Option<u32>,
+    pub memory: Option<usize>,
+    pub load_address: Option<u16>,
+    pub entry: Option<u16>,
+    // "file@address", relative paths are taken from where the emulator runs
+    pub segments: Vec<String>,
+    // a font preset or file, and where it goes in memory
+    pub font: Option<String>,
+    pub font_address: Option<u16>,
+    pub strict: Option<bool>,
+    // off, warn or break
+    pub uninitialized: Option<String>,
+    // a number of calls or "unlimited"
+    pub stack_depth: Option<String>,
+    pub symbols: Option<String>,
+    // extra physical inputs for chip-8 keys, e.g. bind = { "pad:a" = 6 }
+    pub bind: BTreeMap<String, usize>,
+}
+
+// The config file: global settings at the top, then a [rom.<sha1>] table for
+// every ROM that needs something different
+//
+//   platform = "chip8"
+//   palette = "amber"
+//
+//   [rom.2c4c6b2e0f4e0ba5ab9f3b7c2e7b5e8d1c3a2f10]
+//   platform = "schip"
+//   ipf = 30
+//   quirks = { clip = false }
+#[derive(Debug, Default)]
+pub struct Config {
+    pub global: Settings,
+    pub rom: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut table: toml::Table = text.parse().map_err(|err: toml::de::Error| err.to_string())?;
+        let rom = match table.remove("rom") {
+            Some(rom) => rom.try_into().map_err(|err: toml::de::Error| err.to_string())?,
+            None => BTreeMap::new(),
+        };
+        let global = toml::Value::Table(table).try_into().map_err(|err: toml::de::Error| err.to_string())?;
+        Ok(Config { global, rom })
+    }
+
+    // The config file given on the command line, or the default one if there is
+    // one. Only a file that was asked for has to exist.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_config_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("can't read config {}: {}", path.display(), err))?;
+        Config::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    // The settings of a ROM, identified by the SHA-1 of its contents
+    pub fn rom_settings(&self, sha1: &str) -> Option<&Settings> {
+        self.rom.iter()
+            .find(|(hash, _)| hash.eq_ignore_ascii_case(sha1))
+            .map(|(_, settings)| settings)
+    }
+}
+
+impl Settings {
+    // Writes the settings over the options
+    pub fn apply(&self, options: &mut Options) -> Result<(), String> {
+        if let Some(name) = &self.platform {
+            options.platform = Platform::from_name(name)
+                .ok_or(format!("unknown platform {}, expected one of {}", name, PLATFORMS.join(", ")))?;
+            options.quirks = options.platform.quirks();
+        }
+        for (name, enabled) in self.quirks.iter() {
+            options.quirks.set(name, *enabled)?;
+        }
+        if let Some(ipf) = self.ipf {
+            if ipf == 0 {
+                return Err("ipf must be at least 1".to_string());
+            }
+            options.instructions_per_frame = ipf;
+        }
+        if let Some(palette) = &self.palette {
+            options.palette = Palette::from_spec(palette)?;
+        }
+        if let Some(keymap) = &self.keymap {
+            options.keymap = Some(keymap.clone());
+        }
+        if let Some(degrees) = self.rotation {
+            options.orientation.rotation = Rotation::from_degrees(degrees)
+                .ok_or(format!("invalid rotation: {} (expected 0, 90, 180 or 270)", degrees))?;
+        }
+        if let Some(flip) = &self.flip {
+            let axes = match flip.as_str() {
+                "horizontal" => "h",
+                "vertical" => "v",
+                "both" => "hv",
+                "none" => "",
+                axes => axes,
+            };
+            options.orientation.set_flip(axes)?;
+        }
+        if let Some(scale) = self.scale {
+            if scale == 0 {
+                return Err("scale must be at least 1".to_string());
+            }
+            options.scale = scale;
+        }
+        if let Some(size) = self.memory {
+            options.memory_size = Some(size);
+        }
+        if let Some(address) = self.load_address {
+            options.load_address = address;
+        }
+        if let Some(entry) = self.entry {
+            options.entry = Some(entry);
+        }
+        if !self.segments.is_empty() {
+            options.segments = self.segments.iter()
+                .map(|segment| parse_segment(segment))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(font) = &self.font {
+            options.font = with_font(&options.font, font)?;
+        }
+        if let Some(address) = self.font_address {
+            options.font.address = address;
+        }
+        if let Some(strict) = self.strict {
+            options.strict = strict;
+        }
+        if let Some(mode) = &self.uninitialized {
+            options.uninitialized = parse_uninitialized(mode)?;
+        }
+        if let Some(depth) = &self.stack_depth {
+            options.stack_depth = Some(parse_stack_depth(depth)?);
+        }
+        if let Some(symbols) = &self.symbols {
+            options.symbols = Some(symbols.clone());
+        }
+        for (name, key) in self.bind.iter() {
+            if *key > 0xF {
+                return Err(format!("{} is bound to {}, not a chip-8 key (0-15)", name, key));
+            }
+            options.bindings.retain(|(bound, _)| bound != name);
+            options.bindings.push((name.clone(), *key));
+        }
+        Ok(())
+    }
+}
+
+// $XDG_CONFIG_HOME/chip_8_rs/config.toml, or under ~/.config without it
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("chip_8_rs").join("config.toml"))
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+// The default options with the global settings, what the database knows about
+// the ROM and then the ROM's own settings applied, the command line goes on top
+// of these
+pub fn options_for_rom(config: &Config, database: Option<&Database>, rom_data: Option<&[u8]>) -> Result<Options, String> {
+    let mut options = Options::default();
+    config.global.apply(&mut options).map_err(|err| format!("config: {}", err))?;
+    if let Some(data) = rom_data {
+        let sha1 = sha1_hex(data);
+        if let Some(entry) = database.and_then(|database| database.lookup(&sha1)) {
+            entry.settings.apply(&mut options).map_err(|err| format!("database entry {}: {}", entry.title, err))?;
+            options.title = Some(match entry.authors.is_empty() {
+                true => entry.title,
+                false => format!("{} by {}", entry.title, entry.authors.join(", ")),
+            });
+        }
+        if let Some(settings) = config.rom_settings(&sha1) {
+            settings.apply(&mut options).map_err(|err| format!("config for ROM {}: {}", sha1, err))?;
+        }
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+use crate::font::Font;
+#[cfg(test)]
+use crate::platform::StackDepth;
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse(r#"
+        platform = "schip"
+        palette = "amber"
+        quirks = { clip = false }
+
+        [rom.ABCDEF]
+        ipf = 30
+        rotation = 90
+        flip = "vertical"
+        load_address = 0x600
+        segments = ["monitor.bin@0x000"]
+        font = "eti660"
+        font_address = 0x050
+        stack_depth = "unlimited"
+    "#).unwrap();
+    assert_eq!(config.global.platform.as_deref(), Some("schip"));
+    assert_eq!(config.rom_settings("abcdef").unwrap().ipf, Some(30));
+    let mut options = Options::default();
+    config.rom_settings("abcdef").unwrap().apply(&mut options).unwrap();
+    assert_eq!(options.load_address, 0x600);
+    assert!(options.orientation.flip_vertical && !options.orientation.flip_horizontal);
+    assert_eq!(options.segments, vec![("monitor.bin".to_string(), 0)]);
+    assert_eq!(options.font.address, 0x50);
+    assert_eq!(options.font.small, Font::preset("eti660").unwrap().small);
+    assert_eq!(options.stack_depth, Some(StackDepth::Unlimited));
+    assert!(config.rom_settings("012345").is_none());
+
+    let mut options = Options::default();
+    let flip = Config::parse("flip = \"sideways\"").unwrap();
+    assert!(flip.global.apply(&mut options).is_err());
+
+    assert!(Config::parse("speed = 3").is_err());
+    assert!(Config::parse("ipf = \"fast\"").is_err());
+}
+
+#[test]
+fn test_settings_layers() {
+    let rom = b"\x12\x00";
+    let sha1 = sha1_hex(rom);
+    let config = Config::parse(&format!(r#"
+        platform = "schip"
+        ipf = 20
+        quirks = {{ clip = false }}
+
+        [rom.{}]
+        ipf = 30
+        keymap = "azerty"
+    "#, sha1)).unwrap();
+
+    let options = options_for_rom(&config, None, Some(rom)).unwrap();
+    assert_eq!(options.platform, Platform::SuperChip);
+    assert!(options.quirks.jump_vx);
+    assert!(!options.quirks.clip_sprites);
+    assert_eq!(options.instructions_per_frame, 30);
+    assert_eq!(options.keymap.as_deref(), Some("azerty"));
+
+    let options = options_for_rom(&config, None, Some(b"other")).unwrap();
+    assert_eq!(options.instructions_per_frame, 20);
+    assert_eq!(options.keymap, None);
+
+    let broken = Config::parse("palette = \"pink\"").unwrap();
+    assert!(options_for_rom(&broken, None, None).is_err());
+}
+
+#[test]
+fn test_sha1_hex() {
+    assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+}
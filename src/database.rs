@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::config::{default_config_path, Settings};
+
+// A program of the community CHIP-8 database (programs.json), with every known
+// release of it keyed by SHA-1
+#[derive(Debug, Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RomEntry {
+    platforms: Vec<String>,
+    quirky_platforms: HashMap<String, HashMap<String, bool>>,
+    tickrate: Option<u32>,
+    start_address: Option<u16>,
+    screen_rotation: Option<u32>,
+    colors: Option<Colors>,
+    keys: HashMap<String, usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Colors {
+    // background first, then the foreground
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+// What the database knows about a ROM
+#[derive(Debug, PartialEq)]
+pub struct DatabaseEntry {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub settings: Settings,
+}
+
+pub struct Database {
+    programs: Vec<Program>,
+    // SHA-1 of a ROM to the program it belongs to
+    index: HashMap<String, usize>,
+}
+
+impl Database {
+    pub fn parse(json: &str) -> Result<Database, String> {
+        let programs: Vec<Program> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let mut index = HashMap::new();
+        for (number, program) in programs.iter().enumerate() {
+            for sha1 in program.roms.keys() {
+                index.insert(sha1.to_lowercase(), number);
+            }
+        }
+        Ok(Database { programs, index })
+    }
+
+    // The database given on the command line, or the default one if there is
+    // one. Only a file that was asked for has to exist.
+    pub fn load(path: Option<&str>) -> Result<Option<Database>, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_database_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(None),
+            },
+        };
+        let json = fs::read_to_string(&path)
+            .map_err(|err| format!("can't read database {}: {}", path.display(), err))?;
+        Database::parse(&json)
+            .map(Some)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<DatabaseEntry> {
+        let sha1 = sha1.to_lowercase();
+        let program = &self.programs[*self.index.get(&sha1)?];
+        let rom = program.roms.iter()
+            .find(|(hash, _)| hash.to_lowercase() == sha1)
+            .map(|(_, rom)| rom)?;
+        Some(DatabaseEntry {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            settings: rom_settings(rom),
+        })
+    }
+}
+
+// Next to the config file, e.g. ~/.config/chip_8_rs/programs.json
+pub fn default_database_path() -> Option<PathBuf> {
+    Some(default_config_path()?.with_file_name("programs.json"))
+}
+
+// The database's platform names and the platforms they run like here
+fn platform_name(platform: &str) -> Option<&'static str> {
+    match platform {
+        "originalChip8" | "hybridVIP" | "modernChip8" | "chip8x" => Some("chip8"),
+        "chip48" | "superchip1" | "superchip" => Some("schip"),
+        "xochip" => Some("xochip"),
+        _ => None,
+    }
+}
+
+fn rom_settings(rom: &RomEntry) -> Settings {
+    let mut settings = Settings::default();
+    // the first platform listed that can be emulated
+    if let Some((platform, name)) = rom.platforms.iter()
+        .find_map(|platform| platform_name(platform).map(|name| (platform, name))) {
+        settings.platform = Some(name.to_string());
+        if platform == "modernChip8" {
+            settings.quirks.insert("vfreset".to_string(), false);
+        }
+        if let Some(quirks) = rom.quirky_platforms.get(platform) {
+            for (quirk, enabled) in quirks {
+                // the database names the deviation, "wrap" and "memoryLeaveIUnchanged"
+                // are the opposite of the quirks here
+                let (name, enabled) = match quirk.as_str() {
+                    "shift" => ("shift", *enabled),
+                    "jump" => ("jump", *enabled),
+                    "logic" => ("vfreset", *enabled),
+                    "wrap" => ("clip", !*enabled),
+                    "memoryLeaveIUnchanged" => ("memory", !*enabled),
+                    "memoryIncrementByX" => ("memory", true),
+                    _ => continue,
+                };
+                settings.quirks.insert(name.to_string(), enabled);
+            }
+        }
+    }
+    settings.ipf = rom.tickrate.filter(|&ipf| ipf > 0);
+    settings.rotation = rom.screen_rotation;
+    settings.load_address = rom.start_address;
+    if let Some(colors) = &rom.colors {
+        if let [off, on, ..] = colors.pixels.as_slice() {
+            settings.palette = Some(format!("{},{}", on, off));
+        }
+    }
+    for (action, key) in rom.keys.iter() {
+        let inputs: &[&str] = match action.as_str() {
+            "up" => &["pad:dpup", "pad:lefty-"],
+            "down" => &["pad:dpdown", "pad:lefty+"],
+            "left" => &["pad:dpleft", "pad:leftx-"],
+            "right" => &["pad:dpright", "pad:leftx+"],
+            "a" => &["pad:a"],
+            "b" => &["pad:b"],
+            _ => &[],
+        };
+        if *key < 16 {
+            for input in inputs {
+                settings.bind.insert(input.to_string(), *key);
+            }
+        }
+    }
+    settings
+}
+
+#[cfg(test)]
+const TEST_DATABASE: &str = r##"[
+    {
+        "title": "Test Game",
+        "authors": ["Someone"],
+        "roms": {
+            "ABCDEF0123": {
+                "file": "test.ch8",
+                "platforms": ["megachip8", "superchip"],
+                "quirkyPlatforms": { "superchip": { "wrap": true, "vblank": true } },
+                "tickrate": 30,
+                "startAddress": 1536,
+                "colors": { "pixels": ["#000000", "#ffaa00"] },
+                "keys": { "up": 3, "a": 6, "player2Up": 12 }
+            }
+        }
+    },
+    { "title": "Other", "roms": { "1111": { "platforms": ["originalChip8"] } } }
+]"##;
+
+#[test]
+fn test_database_lookup() {
+    let database = Database::parse(TEST_DATABASE).unwrap();
+    let entry = database.lookup("abcdef0123").unwrap();
+    assert_eq!(entry.title, "Test Game");
+    assert_eq!(entry.authors, vec!["Someone"]);
+    assert_eq!(entry.settings.platform.as_deref(), Some("schip"));
+    assert_eq!(entry.settings.quirks.get("clip"), Some(&false));
+    assert_eq!(entry.settings.ipf, Some(30));
+    assert_eq!(entry.settings.load_address, Some(0x600));
+    assert_eq!(entry.settings.palette.as_deref(), Some("#ffaa00,#000000"));
+    assert_eq!(entry.settings.bind.get("pad:dpup"), Some(&3));
+    assert_eq!(entry.settings.bind.get("pad:a"), Some(&6));
+    assert_eq!(entry.settings.bind.len(), 3);
+
+    let other = database.lookup("1111").unwrap();
+    assert_eq!(other.settings.platform.as_deref(), Some("chip8"));
+    assert_eq!(other.settings.ipf, None);
+    assert!(database.lookup("2222").is_none());
+}
+
+#[test]
+fn test_database_settings_apply() {
+    use crate::cli::Options;
+    let database = Database::parse(TEST_DATABASE).unwrap();
+    let mut options = Options::default();
+    database.lookup("abcdef0123").unwrap().settings.apply(&mut options).unwrap();
+    assert!(options.quirks.jump_vx);
+    assert!(!options.quirks.clip_sprites);
+    assert_eq!(options.bindings, vec![("pad:a".to_string(), 6), ("pad:dpup".to_string(), 3), ("pad:lefty-".to_string(), 3)]);
+}
+
+#[test]
+fn test_database_errors() {
+    assert!(Database::parse("{}").is_err());
+    assert!(Database::parse("[{\"roms\": {}}]").is_err());
+}
@@ -1,16 +1,13 @@
-pub trait Disassembler {
-    fn disassemble(&self, opcode: u16);
-}
-
-#[derive(Debug)]
-pub struct NullDisassembler {}
-impl Disassembler for NullDisassembler {
-    fn disassemble(&self, _opcode: u16) {}
-}
+use crate::hooks::Hooks;
 
 #[derive(Debug)]
 pub struct DebugDisassembler{}
-impl Disassembler for DebugDisassembler {
+impl Hooks for DebugDisassembler {
+    fn before_instruction(&mut self, _pc: u16, opcode: u16) {
+        self.disassemble(opcode);
+    }
+}
+impl DebugDisassembler {
     fn disassemble(&self, opcode: u16) {
         let nibbles = (
             ((opcode & 0xF000) >> 12) as u8,
@@ -21,7 +18,7 @@ impl Disassembler for DebugDisassembler {
         let addr = opcode & 0xFFF;
         let vx = nibbles.1;
         let vy = nibbles.2;
-        let kk = (opcode & 0xFF) as u16;
+        let kk = opcode & 0xFF;
 
         match nibbles {
             (0x0, 0x0, 0x0, 0x0) => println!("{:#06x}: Nop", opcode),
@@ -56,9 +53,12 @@ impl Disassembler for DebugDisassembler {
             (0xF,   _, 0x1, 0x8) => println!("{:#06x}: LD ST, v{}", opcode, vx),
             (0xF,   _, 0x1, 0xE) => println!("{:#06x}: ADD I, v{}", opcode, vx),
             (0xF,   _, 0x2, 0x9) => println!("{:#06x}: LD I, [v{}]", opcode, vx),
+            (0xF,   _, 0x3, 0x0) => println!("{:#06x}: LD I, BIGFONT v{}", opcode, vx),
             (0xF,   _, 0x3, 0x3) => println!("{:#06x}: BCD I, v{}", opcode, vx),
             (0xF,   _, 0x5, 0x5) => println!("{:#06x}: LD [I], v{}", opcode, vx),
             (0xF,   _, 0x6, 0x5) => println!("{:#06x}: LD v{}, [I]", opcode, vx),
+            (0xF, 0x0, 0x0, 0x2) => println!("{:#06x}: AUDIO [I]", opcode),
+            (0xF,   _, 0x3, 0xA) => println!("{:#06x}: PITCH v{}", opcode, vx),
             _ => println!("Invalid opcode: {}", opcode),
         };       
     }
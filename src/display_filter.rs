@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+
+use crate::{CHIP_8_HEIGHT, CHIP_8_WIDTH};
+
+// Every chip-8 pixel is expanded to CELL_SIZE x CELL_SIZE texels so that
+// scanlines and the pixel grid can be drawn inside a pixel
+pub const CELL_SIZE: usize = 4;
+
+pub const DEFAULT_DECAY: f32 = 0.6;
+pub const DEFAULT_BLEND_FRAMES: usize = 3;
+
+// Brightness of the texels darkened by scanlines or the pixel grid
+const LINE_SHADE: f32 = 0.45;
+// Below this a fading pixel is considered off
+const MIN_INTENSITY: f32 = 0.01;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Filter {
+    Persistence,
+    Blending,
+    Scanlines,
+    PixelGrid,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "persistence" => Some(Filter::Persistence),
+            "blend" => Some(Filter::Blending),
+            "scanlines" => Some(Filter::Scanlines),
+            "grid" => Some(Filter::PixelGrid),
+            _ => None,
+        }
+    }
+}
+
+pub struct DisplayFilters {
+    pub persistence: bool,
+    // Fraction of its brightness a pixel keeps every frame after turning off
+    pub decay: f32,
+    pub blending: bool,
+    pub blend_frames: usize,
+    pub scanlines: bool,
+    pub pixel_grid: bool,
+    intensity: Vec<f32>,
+    history: VecDeque<Vec<f32>>,
+}
+
+impl DisplayFilters {
+    pub fn new(decay: f32, blend_frames: usize) -> Self {
+        DisplayFilters {
+            persistence: false,
+            decay,
+            blending: false,
+            blend_frames: blend_frames.max(1),
+            scanlines: false,
+            pixel_grid: false,
+            intensity: vec![0.0; CHIP_8_WIDTH * CHIP_8_HEIGHT],
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn toggle(&mut self, filter: Filter) {
+        match filter {
+            Filter::Persistence => self.persistence = !self.persistence,
+            Filter::Blending => {
+                self.blending = !self.blending;
+                self.history.clear();
+            }
+            Filter::Scanlines => self.scanlines = !self.scanlines,
+            Filter::PixelGrid => self.pixel_grid = !self.pixel_grid,
+        }
+    }
+
+    // True while the picture keeps changing without new vram contents
+    pub fn is_animating(&self) -> bool {
+        let fading = self.persistence && self.intensity.iter().any(|&i| i > MIN_INTENSITY && i < 1.0);
+        let blending = self.blending && self.history.iter().any(|frame| *frame != self.intensity);
+        fading || blending
+    }
+
+    // Turns the vram into per pixel brightness between 0.0 and 1.0, row major
+    pub fn process(&mut self, vram: &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT]) -> Vec<f32> {
+        for (y, row) in vram.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let intensity = &mut self.intensity[y * CHIP_8_WIDTH + x];
+                *intensity = if pixel > 0 {
+                    1.0
+                } else if self.persistence && *intensity > MIN_INTENSITY {
+                    *intensity * self.decay
+                } else {
+                    0.0
+                };
+            }
+        }
+
+        if !self.blending {
+            return self.intensity.clone();
+        }
+
+        self.history.push_back(self.intensity.clone());
+        while self.history.len() > self.blend_frames {
+            self.history.pop_front();
+        }
+        let mut blended = vec![0.0; self.intensity.len()];
+        for frame in self.history.iter() {
+            for (out, value) in blended.iter_mut().zip(frame) {
+                *out += value / self.history.len() as f32;
+            }
+        }
+        blended
+    }
+
+    // Brightness multiplier of a texel at (cell_x, cell_y) inside a pixel's cell
+    pub fn cell_shade(&self, cell_x: usize, cell_y: usize) -> f32 {
+        let last = CELL_SIZE - 1;
+        if self.scanlines && cell_y == last {
+            return LINE_SHADE;
+        }
+        if self.pixel_grid && (cell_x == last || cell_y == last) {
+            return LINE_SHADE;
+        }
+        1.0
+    }
+}
+
+#[cfg(test)]
+fn vram_with_pixel(on: bool) -> [[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT] {
+    let mut vram = [[0; CHIP_8_WIDTH]; CHIP_8_HEIGHT];
+    vram[0][0] = if on { 0xFF } else { 0 };
+    vram
+}
+
+#[test]
+fn test_no_filters() {
+    let mut filters = DisplayFilters::new(DEFAULT_DECAY, DEFAULT_BLEND_FRAMES);
+    assert_eq!(filters.process(&vram_with_pixel(true))[0], 1.0);
+    assert_eq!(filters.process(&vram_with_pixel(false))[0], 0.0);
+    assert!(!filters.is_animating());
+}
+
+#[test]
+fn test_persistence() {
+    let mut filters = DisplayFilters::new(0.5, DEFAULT_BLEND_FRAMES);
+    filters.toggle(Filter::Persistence);
+    filters.process(&vram_with_pixel(true));
+    assert_eq!(filters.process(&vram_with_pixel(false))[0], 0.5);
+    assert_eq!(filters.process(&vram_with_pixel(false))[0], 0.25);
+    assert!(filters.is_animating());
+}
+
+#[test]
+fn test_blending() {
+    let mut filters = DisplayFilters::new(DEFAULT_DECAY, 2);
+    filters.toggle(Filter::Blending);
+    assert_eq!(filters.process(&vram_with_pixel(true))[0], 1.0);
+    assert_eq!(filters.process(&vram_with_pixel(false))[0], 0.5);
+    assert!(filters.is_animating());
+    assert_eq!(filters.process(&vram_with_pixel(false))[0], 0.0);
+    assert!(!filters.is_animating());
+}
+
+#[test]
+fn test_cell_shade() {
+    let mut filters = DisplayFilters::new(DEFAULT_DECAY, DEFAULT_BLEND_FRAMES);
+    assert_eq!(filters.cell_shade(CELL_SIZE - 1, CELL_SIZE - 1), 1.0);
+    filters.toggle(Filter::Scanlines);
+    assert_eq!(filters.cell_shade(0, CELL_SIZE - 1), LINE_SHADE);
+    assert_eq!(filters.cell_shade(CELL_SIZE - 1, 0), 1.0);
+    filters.toggle(Filter::PixelGrid);
+    assert_eq!(filters.cell_shade(CELL_SIZE - 1, 0), LINE_SHADE);
+}
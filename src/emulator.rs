@@ -0,0 +1,616 @@
+use crate::audio::{AudioOutput, AudioSink, SAMPLE_RATE};
+use crate::disassembler::DebugDisassembler;
+use crate::font::Font;
+use crate::hooks::{Hooks, NoHooks};
+use crate::memory::{MemoryBus, UninitializedCheck, DEFAULT_LOAD_ADDRESS};
+use crate::platform::{Platform, Quirks, StackDepth};
+use crate::processor::{BusyWait, CpuState, Fault, Processor, Snapshot};
+use crate::symbols::Symbols;
+use crate::tone::ToneSettings;
+use crate::{CHIP_8_HEIGHT, CHIP_8_WIDTH, INSTRUCTIONS_PER_FRAME};
+
+// Where the interpreter's area ends, strict mode traps writes below it
+const INTERPRETER_END: u16 = 0x200;
+
+pub struct FrameOutput {
+    pub vram_changed: bool,
+    // Whether the buzzer is on at the end of the frame
+    pub beep: bool,
+    // Faults with the PC, the frame stops at the first one
+    pub faults: Vec<(u16, Fault)>,
+    // The program reached a jump to itself
+    pub halted: bool,
+}
+
+// A complete machine: processor, memory and sound, run a 60 Hz frame at a time.
+// Every frontend drives one of these.
+pub struct Emulator {
+    cpu: Processor,
+    audio: AudioOutput,
+    instructions_per_frame: u32,
+    memory_size: usize,
+    // writes below this address are trapped, memory is not strict without it
+    protected_end: Option<u16>,
+    // font, ROM and segments with their addresses, in the order they were loaded
+    images: Vec<(u16, Vec<u8>)>,
+    // names for the backtrace
+    symbols: Symbols,
+}
+
+pub struct EmulatorBuilder {
+    platform: Platform,
+    quirks: Option<Quirks>,
+    seed: Option<u64>,
+    instructions_per_frame: u32,
+    font: Font,
+    load_address: u16,
+    entry: Option<u16>,
+    memory_size: Option<usize>,
+    stack_depth: Option<StackDepth>,
+    rom: Option<(String, Vec<u8>)>,
+    segments: Vec<(String, Vec<u8>, u16)>,
+    debug: bool,
+    trace: bool,
+    strict: bool,
+    uninitialized: UninitializedCheck,
+    symbols: Symbols,
+    hooks: Vec<Box<dyn Hooks>>,
+    tone: ToneSettings,
+    sample_rate: u32,
+}
+
+impl EmulatorBuilder {
+    // The platform's own quirks unless others are given
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn instructions_per_frame(mut self, instructions: u32) -> Self {
+        self.instructions_per_frame = instructions;
+        self
+    }
+
+    pub fn font(mut self, font: Font) -> Self {
+        self.font = font;
+        self
+    }
+
+    pub fn load_address(mut self, address: u16) -> Self {
+        self.load_address = address;
+        self
+    }
+
+    // Where execution starts, the load address when not given
+    pub fn entry(mut self, entry: Option<u16>) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    // The platform's full memory when not given
+    pub fn memory_size(mut self, size: Option<usize>) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    // The platform's stack depth when not given
+    pub fn stack_depth(mut self, depth: Option<StackDepth>) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    // The program, the name is only used in error messages
+    pub fn rom(mut self, name: &str, data: Vec<u8>) -> Self {
+        self.rom = Some((name.to_string(), data));
+        self
+    }
+
+    // More data loaded after the ROM, e.g. a monitor program
+    pub fn segment(mut self, name: &str, data: Vec<u8>, address: u16) -> Self {
+        self.segments.push((name.to_string(), data, address));
+        self
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    // Trap bad memory accesses instead of making them
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Report reads of memory and registers the program never set
+    pub fn uninitialized(mut self, check: UninitializedCheck) -> Self {
+        self.uninitialized = check;
+        self
+    }
+
+    pub fn symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    // Called as instructions run, after the disassembler of debug
+    #[allow(dead_code)]
+    pub fn hooks(mut self, hooks: Box<dyn Hooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
+
+    pub fn tone(mut self, tone: ToneSettings) -> Self {
+        self.tone = tone;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn build(self) -> Result<Emulator, String> {
+        let (name, rom) = self.rom.ok_or("no ROM given")?;
+        let memory_size = self.platform.memory_size(self.memory_size)?;
+        let font = self.font.small.iter().chain(self.font.big.iter()).copied().collect();
+        let images: Vec<_> = [(self.font.address, font), (self.load_address, rom)].into_iter()
+            .chain(self.segments.iter().map(|(_, data, address)| (*address, data.clone())))
+            .collect();
+        let names: Vec<_> = ["font", name.as_str()].into_iter()
+            .chain(self.segments.iter().map(|(name, _, _)| name.as_str()))
+            .collect();
+        let protected_end = self.strict.then_some(self.load_address.min(INTERPRETER_END));
+        let mut bus = fresh_bus(memory_size, protected_end);
+        for ((address, data), name) in images.iter().zip(names) {
+            bus.load_bytes(name, data, *address).map_err(|err| err.to_string())?;
+        }
+
+        let entry = self.entry.unwrap_or(self.load_address);
+        if entry as usize >= memory_size {
+            return Err(format!("entry point {:#06x} is outside of memory", entry));
+        }
+        let mut hooks = self.hooks;
+        if self.debug {
+            hooks.insert(0, Box::new(DebugDisassembler{}));
+        }
+        let hooks: Box<dyn Hooks> = match hooks.is_empty() {
+            true => Box::new(NoHooks),
+            false => Box::new(hooks),
+        };
+        let mut cpu = Processor::new(bus, hooks)
+            .with_quirks(self.quirks.unwrap_or(self.platform.quirks()))
+            .with_start_address(entry)
+            .with_font_address(self.font.address)
+            .with_stack_depth(self.stack_depth.unwrap_or(self.platform.stack_depth()))
+            .with_uninitialized_check(self.uninitialized)
+            .with_trace(self.trace);
+        if let Some(seed) = self.seed {
+            cpu = cpu.with_seed(seed);
+        }
+        Ok(Emulator {
+            cpu,
+            audio: AudioOutput::new(self.tone, self.sample_rate),
+            instructions_per_frame: self.instructions_per_frame,
+            memory_size,
+            protected_end,
+            images,
+            symbols: self.symbols,
+        })
+    }
+}
+
+impl Emulator {
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder {
+            platform: Platform::default(),
+            quirks: None,
+            seed: None,
+            instructions_per_frame: INSTRUCTIONS_PER_FRAME,
+            font: Font::default(),
+            load_address: DEFAULT_LOAD_ADDRESS,
+            entry: None,
+            memory_size: None,
+            stack_depth: None,
+            rom: None,
+            segments: Vec::new(),
+            debug: false,
+            trace: false,
+            strict: false,
+            uninitialized: UninitializedCheck::Off,
+            symbols: Symbols::default(),
+            hooks: Vec::new(),
+            tone: ToneSettings::default(),
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    // Runs one 60 Hz frame worth of instructions, counts the timers down once and
+    // renders the frame's sound
+    pub fn run_frame(&mut self, keyboard: &[bool; 16]) -> FrameOutput {
+        let mut frame = FrameOutput { vram_changed: false, beep: false, faults: Vec::new(), halted: false };
+        for _ in 0..self.instructions_per_frame {
+            let output = self.cpu.tick(keyboard);
+            frame.vram_changed |= output.vram_changed;
+            frame.beep = output.beep;
+            let audio_pattern_changed = output.audio_pattern_changed;
+            let busy_wait = output.busy_wait;
+            if let Some(value) = output.sound_timer_set {
+                self.audio.set_sound_timer(value);
+            }
+            if audio_pattern_changed {
+                self.audio.set_pattern(self.cpu.audio_pattern());
+            }
+            frame.faults = self.cpu.take_faults();
+            if !frame.faults.is_empty() {
+                break;
+            }
+            // the rest of the frame would go round the same loop, skip to the timers
+            if let Some(busy_wait) = busy_wait {
+                frame.halted = busy_wait == BusyWait::Halted;
+                break;
+            }
+        }
+        self.cpu.tick_timers();
+        self.audio.render_frame();
+        frame
+    }
+
+    // The registers, stack and screen, the processor's mutators change them
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
+    #[allow(dead_code)]
+    pub fn cpu_mut(&mut self) -> &mut Processor {
+        &mut self.cpu
+    }
+
+    // The calls that led to the current instruction, innermost first
+    pub fn backtrace(&self) -> Vec<String> {
+        self.cpu.backtrace(&self.symbols)
+    }
+
+    // Like the processor's set_timers, with the sound timer also starting the buzzer
+    #[allow(dead_code)]
+    pub fn set_timers(&mut self, delay_timer: u8, sound_timer: u8) {
+        self.cpu.set_timers(delay_timer, sound_timer);
+        self.audio.set_sound_timer(sound_timer);
+    }
+
+    pub fn framebuffer(&self) -> &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT] {
+        self.cpu.vram()
+    }
+
+    // The sound of the last frame, sinks get every frame of it
+    pub fn audio_samples(&self) -> &[f32] {
+        self.audio.samples()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate()
+    }
+
+    pub fn add_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio.add_sink(sink);
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions;
+    }
+
+    // Back to the power on state with fresh memory
+    pub fn reset(&mut self) {
+        *self.cpu.bus_mut() = fresh_bus(self.memory_size, self.protected_end);
+        self.soft_reset();
+    }
+
+    // Reloads the program and restarts it, memory it doesn't cover is left alone
+    pub fn soft_reset(&mut self) {
+        for (address, data) in self.images.iter() {
+            // these all fit when the emulator was built
+            self.cpu.bus_mut().load_bytes("", data, *address).unwrap();
+        }
+        self.cpu.reset();
+        self.audio.reset();
+    }
+
+    // For embedders, e.g. rewinding or save states
+    #[allow(dead_code)]
+    pub fn snapshot(&self) -> Snapshot {
+        self.cpu.snapshot()
+    }
+
+    #[allow(dead_code)]
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu.restore(snapshot);
+        self.audio.reset();
+        self.audio.set_sound_timer(self.cpu.sound_timer());
+        self.audio.set_pattern(self.cpu.audio_pattern());
+    }
+}
+
+pub fn fault_message(pc: u16, fault: Fault) -> String {
+    format!("PC {:#05x}: {}", pc, fault)
+}
+
+// Empty memory, strict when there is a protected area
+fn fresh_bus(size: usize, protected_end: Option<u16>) -> MemoryBus {
+    let mut bus = MemoryBus::with_size(size);
+    if let Some(protected_end) = protected_end {
+        bus.set_strict(protected_end);
+    }
+    bus
+}
+
+#[cfg(test)]
+fn test_emulator(program: &[u8]) -> Emulator {
+    Emulator::builder().rom("test", program.to_vec()).build().unwrap()
+}
+
+#[test]
+fn test_run_frame_sound() {
+    // LD V0, 2; LD ST, V0; JP 0x204
+    let mut emulator = test_emulator(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]);
+    let keyboard = [false; 16];
+
+    assert!(emulator.run_frame(&keyboard).beep);
+    assert!(emulator.audio_samples().iter().any(|&s| s != 0.0));
+    emulator.run_frame(&keyboard);
+    // two frames of sound and a short fade out
+    emulator.run_frame(&keyboard);
+    emulator.run_frame(&keyboard);
+    assert!(emulator.audio_samples().iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn test_builder() {
+    use crate::memory::Bus;
+
+    assert!(Emulator::builder().build().is_err());
+    assert!(Emulator::builder().rom("empty", Vec::new()).build().is_err());
+    assert!(Emulator::builder().rom("test", vec![0x12, 0x00]).entry(Some(0x1000)).build().is_err());
+    let emulator = Emulator::builder()
+        .platform(Platform::XoChip)
+        .rom("test", vec![0x12, 0x00])
+        .segment("data", vec![0xAA], 0x8000)
+        .build()
+        .unwrap();
+    assert_eq!(emulator.snapshot().bus().read_byte(0x8000), 0xAA);
+}
+
+#[test]
+fn test_snapshot_restore() {
+    // LD V0, 1; ADD V0, 1; DRAW V0, V0, 5; JP 0x202
+    let mut emulator = test_emulator(&[0x60, 0x01, 0x70, 0x01, 0xD0, 0x05, 0x12, 0x02]);
+    let keyboard = [false; 16];
+    emulator.run_frame(&keyboard);
+    let snapshot = emulator.snapshot();
+    let framebuffer = *emulator.framebuffer();
+    emulator.run_frame(&keyboard);
+    assert_ne!(*emulator.framebuffer(), framebuffer);
+    emulator.restore(&snapshot);
+    assert_eq!(*emulator.framebuffer(), framebuffer);
+}
+
+#[test]
+fn test_set_timers() {
+    // JP 0x200
+    let mut emulator = test_emulator(&[0x12, 0x00]);
+    emulator.set_timers(5, 3);
+    let frame = emulator.run_frame(&[false; 16]);
+    assert!(frame.beep);
+    assert!(emulator.audio_samples().iter().any(|&s| s != 0.0));
+    assert_eq!((emulator.cpu_state().delay_timer, emulator.cpu_state().sound_timer), (4, 2));
+}
+
+#[test]
+fn test_reset() {
+    use crate::memory::Bus;
+
+    // LD V0, 0xFF; LD I, 0x300; LD [I], V0; JP 0x208
+    let mut emulator = test_emulator(&[0x60, 0xFF, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x08]);
+    // enough for the store at any speed
+    for _ in 0..2 {
+        emulator.run_frame(&[false; 16]);
+    }
+    emulator.soft_reset();
+    assert_eq!(emulator.snapshot().bus().read_byte(0x300), 0xFF);
+    emulator.reset();
+    assert_eq!(emulator.snapshot().bus().read_byte(0x300), 0x00);
+    assert_eq!(emulator.snapshot().bus().read_byte(0x200), 0x60);
+}
+
+// Hooks writing down what happened, shared with the test
+#[cfg(test)]
+struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+#[cfg(test)]
+impl Hooks for Recorder {
+    fn before_instruction(&mut self, pc: u16, _opcode: u16) {
+        self.0.borrow_mut().push(format!("{:03X}", pc));
+    }
+    fn memory_write(&mut self, address: u16, value: u8) {
+        self.0.borrow_mut().push(format!("write {:03X} {}", address, value));
+    }
+    fn draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        self.0.borrow_mut().push(format!("draw {} {} {} {}", x, y, height, collision));
+    }
+    fn key_wait(&mut self, register: usize) {
+        self.0.borrow_mut().push(format!("wait v{}", register));
+    }
+}
+
+#[test]
+fn test_hooks() {
+    // LD V0, 7; LD I, 0x300; BCD V0; DRAW V0, V0, 1; LD V1, key
+    let events = Default::default();
+    let mut emulator = Emulator::builder()
+        .rom("test", vec![0x60, 0x07, 0xA3, 0x00, 0xF0, 0x33, 0xD0, 0x01, 0xF1, 0x0A])
+        .hooks(Box::new(Recorder(std::rc::Rc::clone(&events))))
+        .instructions_per_frame(6)
+        .build()
+        .unwrap();
+    emulator.run_frame(&[false; 16]);
+    assert_eq!(*events.borrow(), [
+        "200", "202", "204", "write 302 7", "write 301 0", "write 300 0", "206", "draw 7 7 1 false",
+        "208", "wait v1", "208",
+    ]);
+    let mut keyboard = [false; 16];
+    keyboard[0xC] = true;
+    emulator.run_frame(&keyboard);
+    assert_eq!(emulator.cpu_state().v[1], 0xC);
+}
+
+#[test]
+fn test_strict_memory() {
+    use crate::memory::{Bus, MemoryViolation};
+
+    // LD I, 0x050; LD [I], V0; JP 0x204
+    let program = vec![0xA0, 0x50, 0xF0, 0x55, 0x12, 0x04];
+    let mut strict = Emulator::builder().rom("test", program.clone()).strict(true).build().unwrap();
+    let frame = strict.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x202, Fault::Memory(MemoryViolation::InterpreterWrite(0x050)))]);
+    // the font is left alone
+    assert_eq!(strict.snapshot().bus().read_byte(0x050), 0x3C);
+    assert_eq!(strict.cpu_state().pc, 0x204);
+
+    let mut lenient = Emulator::builder().rom("test", program).build().unwrap();
+    assert!(lenient.run_frame(&[false; 16]).faults.is_empty());
+    assert_eq!(lenient.snapshot().bus().read_byte(0x050), 0x00);
+
+    // JP 0x300 runs off the end of the program
+    let mut strict = Emulator::builder().rom("test", vec![0x13, 0x00]).strict(true).build().unwrap();
+    let frame = strict.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x300, Fault::Memory(MemoryViolation::ExecutePastProgram(0x300)))]);
+    strict.reset();
+    assert_eq!(strict.run_frame(&[false; 16]).faults.len(), 1);
+}
+
+#[test]
+fn test_i_past_memory() {
+    use crate::memory::MemoryViolation;
+
+    // LD I, 0xFFF; DRW V0, V0, 2; LD B, V0; JP 0x206
+    let program = vec![0xAF, 0xFF, 0xD0, 0x02, 0xF0, 0x33, 0x12, 0x06];
+    let mut lenient = Emulator::builder().rom("test", program.clone()).build().unwrap();
+    assert!(lenient.run_frame(&[false; 16]).faults.is_empty());
+
+    // FX1E can leave I at 0xFFFF, the second row is at 0x0000
+    let mut strict = Emulator::builder().rom("test", program[2..].to_vec()).strict(true).build().unwrap();
+    strict.cpu_mut().set_i(0xFFFF);
+    let frame = strict.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x200, Fault::Memory(MemoryViolation::OutOfBounds(0xFFFF)))]);
+}
+
+#[test]
+fn test_uninitialized_reads() {
+    use crate::memory::MemoryViolation;
+
+    // ADD V3, 1; LD I, 0x300; LD V0, [I]; JP 0x206
+    let program = vec![0x73, 0x01, 0xA3, 0x00, 0xF0, 0x65, 0x12, 0x06];
+    let mut emulator = Emulator::builder()
+        .rom("test", program.clone())
+        .uninitialized(UninitializedCheck::Break)
+        .build()
+        .unwrap();
+    let frame = emulator.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x200, Fault::Memory(MemoryViolation::UninitializedRegister(3)))]);
+    let frame = emulator.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x204, Fault::Memory(MemoryViolation::UninitializedRead(0x300)))]);
+    // each is reported once
+    assert!(emulator.run_frame(&[false; 16]).faults.is_empty());
+
+    let mut unchecked = Emulator::builder().rom("test", program).build().unwrap();
+    assert!(unchecked.run_frame(&[false; 16]).faults.is_empty());
+}
+
+#[test]
+fn test_call_stack() {
+    // CALL 0x206; JP 0x202; outer: CALL 0x20C; RET; inner: RET
+    let program = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x22, 0x0C, 0x00, 0xEE, 0x00, 0x00, 0x00, 0xEE];
+    let symbols = Symbols::parse("0x206 outer\n0x20C inner").unwrap();
+    let mut emulator = Emulator::builder()
+        .rom("test", program.to_vec())
+        .symbols(symbols)
+        .instructions_per_frame(2)
+        .build()
+        .unwrap();
+    let keyboard = [false; 16];
+
+    emulator.run_frame(&keyboard);
+    assert_eq!(emulator.cpu_state().stack, vec![0x200, 0x206]);
+    assert_eq!(emulator.backtrace(), vec!["#0 0x20c in inner", "#1 0x206 in outer", "#2 0x200 in start"]);
+
+    emulator.set_instructions_per_frame(1);
+    emulator.run_frame(&keyboard);
+    assert_eq!(emulator.backtrace(), vec!["#0 0x208 in outer+0x2", "#1 0x200 in start"]);
+    emulator.run_frame(&keyboard);
+    assert_eq!(emulator.backtrace(), vec!["#0 0x202 in start+0x2"]);
+}
+
+#[test]
+fn test_stack_overflow_and_underflow() {
+    // CALL 0x200 forever
+    let mut emulator = Emulator::builder()
+        .rom("test", vec![0x22, 0x00])
+        .instructions_per_frame(20)
+        .build()
+        .unwrap();
+    let frame = emulator.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x200, Fault::StackOverflow { subroutine: 0x200, depth: 12 })]);
+    assert_eq!(emulator.cpu_state().sp, 12);
+    assert_eq!(emulator.backtrace()[0], "#0 0x200 in start");
+    assert_eq!(emulator.backtrace().len(), 13);
+
+    let mut emulator = Emulator::builder()
+        .rom("test", vec![0x22, 0x00])
+        .stack_depth(Some(StackDepth::Unlimited))
+        .instructions_per_frame(100)
+        .build()
+        .unwrap();
+    assert!(emulator.run_frame(&[false; 16]).faults.is_empty());
+    assert_eq!(emulator.cpu_state().sp, 100);
+
+    // RET
+    let mut emulator = test_emulator(&[0x00, 0xEE]);
+    let frame = emulator.run_frame(&[false; 16]);
+    assert_eq!(frame.faults, vec![(0x200, Fault::StackUnderflow)]);
+}
+
+#[test]
+fn test_busy_waits() {
+    // LD V0, 2; LD DT, V0; wait: LD V1, DT; SE V1, 0; JP wait; JP self
+    let instructions = Default::default();
+    let mut emulator = Emulator::builder()
+        .rom("test", vec![0x60, 0x02, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x12, 0x0A])
+        .hooks(Box::new(Recorder(std::rc::Rc::clone(&instructions))))
+        .instructions_per_frame(100)
+        .build()
+        .unwrap();
+    let keyboard = [false; 16];
+
+    assert!(!emulator.run_frame(&keyboard).halted);
+    assert_eq!(instructions.borrow().len(), 5);
+    assert!(!emulator.run_frame(&keyboard).halted);
+    assert_eq!(instructions.borrow().len(), 8);
+    // the timer is at 0, through the skip to the jump to itself
+    assert!(emulator.run_frame(&keyboard).halted);
+    assert_eq!(instructions.borrow().len(), 11);
+    assert_eq!(emulator.cpu_state().pc, 0x20A);
+}
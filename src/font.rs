@@ -1,6 +1,15 @@
-#![allow(unused)]
+use std::fs;
 
-pub const FONT: [u8; 80] = [
+// Bytes per glyph of the 4x5 font (FX29) and of the 8x10 font (FX30)
+const SMALL_GLYPH_SIZE: u16 = 5;
+const BIG_GLYPH_SIZE: u16 = 10;
+const SMALL_FONT_SIZE: usize = 16 * SMALL_GLYPH_SIZE as usize;
+const BIG_FONT_SIZE: usize = 16 * BIG_GLYPH_SIZE as usize;
+
+pub const DEFAULT_FONT_ADDRESS: u16 = 0x000;
+
+// The CHIP-48/SUPER-CHIP font most modern interpreters use
+pub const FONT: [u8; SMALL_FONT_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -16,5 +25,202 @@ pub const FONT: [u8; 80] = [
     0xF0, 0x80, 0x80, 0x80, 0xF0, // C
     0xE0, 0x90, 0x90, 0x90, 0xE0, // D
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0xB0, // E
-];
\ No newline at end of file
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The font in the COSMAC VIP's CHIP-8 interpreter
+const VIP_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// 3 pixel wide digits of the DREAM 6800
+const DREAM_6800_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+const ETI_660_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// The rounder font of the FISH'N'CHIPS interpreter
+const FISH_N_CHIPS_FONT: [u8; SMALL_FONT_SIZE] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// SUPER-CHIP 1.1's big digits, SUPER-CHIP has no big letters so A-F are Octo's
+pub const BIG_FONT: [u8; BIG_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+pub const FONTS: [&str; 5] = ["schip", "vip", "dream6800", "eti660", "fishnchips"];
+
+// The glyphs FX29 and FX30 point at and where they are in memory, the big
+// font follows right after the small one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    pub small: Vec<u8>,
+    pub big: Vec<u8>,
+    pub address: u16,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font { small: FONT.to_vec(), big: BIG_FONT.to_vec(), address: DEFAULT_FONT_ADDRESS }
+    }
+}
+
+impl Font {
+    pub fn preset(name: &str) -> Option<Font> {
+        let small = match name {
+            "schip" => FONT,
+            "vip" => VIP_FONT,
+            "dream6800" => DREAM_6800_FONT,
+            "eti660" => ETI_660_FONT,
+            "fishnchips" => FISH_N_CHIPS_FONT,
+            _ => return None,
+        };
+        Some(Font { small: small.to_vec(), ..Font::default() })
+    }
+
+    // A preset name or a font file: 80 bytes of small glyphs, optionally
+    // followed by 160 bytes of big ones
+    pub fn from_name_or_file(name: &str) -> Result<Font, String> {
+        if let Some(font) = Font::preset(name) {
+            return Ok(font);
+        }
+        let data = fs::read(name)
+            .map_err(|err| format!("unknown font {}, expected a file or one of {} ({})", name, FONTS.join(", "), err))?;
+        Font::from_bytes(&data).map_err(|err| format!("{}: {}", name, err))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Font, String> {
+        match data.len() {
+            SMALL_FONT_SIZE => Ok(Font { small: data.to_vec(), ..Font::default() }),
+            len if len == SMALL_FONT_SIZE + BIG_FONT_SIZE => Ok(Font {
+                small: data[..SMALL_FONT_SIZE].to_vec(),
+                big: data[SMALL_FONT_SIZE..].to_vec(),
+                address: DEFAULT_FONT_ADDRESS,
+            }),
+            len => Err(format!("a font is {} or {} bytes, not {}", SMALL_FONT_SIZE, SMALL_FONT_SIZE + BIG_FONT_SIZE, len)),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.small.len() + self.big.len()
+    }
+}
+
+// Where FX29 points I for a digit, with the font at the given address
+pub fn small_glyph(address: u16, digit: u8) -> u16 {
+    address + (digit & 0xF) as u16 * SMALL_GLYPH_SIZE
+}
+
+// Where FX30 points I, the big glyphs come after the small ones
+pub fn big_glyph(address: u16, digit: u8) -> u16 {
+    address + SMALL_FONT_SIZE as u16 + (digit & 0xF) as u16 * BIG_GLYPH_SIZE
+}
+
+#[test]
+fn test_font_presets() {
+    for name in FONTS {
+        let font = Font::preset(name).unwrap();
+        assert_eq!(font.small.len(), SMALL_FONT_SIZE);
+        assert_eq!(font.big.len(), BIG_FONT_SIZE);
+    }
+    assert!(Font::preset("comic").is_none());
+    // the last row of F is a single pixel on the left
+    assert_eq!(FONT[79], 0x80);
+}
+
+#[test]
+fn test_font_glyphs() {
+    assert_eq!(small_glyph(0x50, 0x0), 0x50);
+    assert_eq!(small_glyph(0x50, 0xA), 0x50 + 50);
+    assert_eq!(big_glyph(0x50, 0x1), 0x50 + 80 + 10);
+    assert_eq!(small_glyph(0, 0x1A), small_glyph(0, 0xA));
+}
+
+#[test]
+fn test_font_from_bytes() {
+    let font = Font::from_bytes(&[0xAA; 80]).unwrap();
+    assert_eq!(font.small, vec![0xAA; 80]);
+    assert_eq!(font.big, BIG_FONT.to_vec());
+    let font = Font::from_bytes(&[0x55; 240]).unwrap();
+    assert_eq!(font.big, vec![0x55; 160]);
+    assert!(Font::from_bytes(&[0; 100]).is_err());
+}
@@ -0,0 +1,25 @@
+use std::{thread, time::{Duration, Instant}};
+
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Paces a frontend's main loop to 60 frames per second
+pub struct FrameTimer {
+    next_frame: Instant,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        FrameTimer { next_frame: Instant::now() }
+    }
+
+    pub fn wait(&mut self) {
+        self.next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            // running behind, don't try to catch up on lost frames
+            self.next_frame = now;
+        }
+    }
+}
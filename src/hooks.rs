@@ -0,0 +1,70 @@
+use crate::processor::Fault;
+
+// Which timer an instruction loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    Delay,
+    Sound,
+}
+
+// Callbacks the processor makes while it runs, for tracers, profilers, coverage
+// tools and scripts. Every hook does nothing unless implemented.
+pub trait Hooks {
+    fn before_instruction(&mut self, _pc: u16, _opcode: u16) {}
+    fn after_instruction(&mut self, _pc: u16, _opcode: u16) {}
+    // memory accessed by instructions, not the instruction fetch
+    fn memory_read(&mut self, _address: u16, _value: u8) {}
+    fn memory_write(&mut self, _address: u16, _value: u8) {}
+    // a DXYN at the wrapped start position
+    fn draw(&mut self, _x: u8, _y: u8, _height: u8, _collision: bool) {}
+    fn timer_write(&mut self, _timer: Timer, _value: u8) {}
+    // FX0A started waiting for a key to go into VX
+    fn key_wait(&mut self, _register: usize) {}
+    fn invalid_opcode(&mut self, _pc: u16, _opcode: u16) {}
+    // something that stops the machine, e.g. an access a strict bus refused
+    fn fault(&mut self, _pc: u16, _fault: Fault) {}
+}
+
+// For a processor nothing watches
+pub struct NoHooks;
+
+impl Hooks for NoHooks {}
+
+// Several hooks, called in order
+impl Hooks for Vec<Box<dyn Hooks>> {
+    fn before_instruction(&mut self, pc: u16, opcode: u16) {
+        self.iter_mut().for_each(|hooks| hooks.before_instruction(pc, opcode));
+    }
+
+    fn after_instruction(&mut self, pc: u16, opcode: u16) {
+        self.iter_mut().for_each(|hooks| hooks.after_instruction(pc, opcode));
+    }
+
+    fn memory_read(&mut self, address: u16, value: u8) {
+        self.iter_mut().for_each(|hooks| hooks.memory_read(address, value));
+    }
+
+    fn memory_write(&mut self, address: u16, value: u8) {
+        self.iter_mut().for_each(|hooks| hooks.memory_write(address, value));
+    }
+
+    fn draw(&mut self, x: u8, y: u8, height: u8, collision: bool) {
+        self.iter_mut().for_each(|hooks| hooks.draw(x, y, height, collision));
+    }
+
+    fn timer_write(&mut self, timer: Timer, value: u8) {
+        self.iter_mut().for_each(|hooks| hooks.timer_write(timer, value));
+    }
+
+    fn key_wait(&mut self, register: usize) {
+        self.iter_mut().for_each(|hooks| hooks.key_wait(register));
+    }
+
+    fn invalid_opcode(&mut self, pc: u16, opcode: u16) {
+        self.iter_mut().for_each(|hooks| hooks.invalid_opcode(pc, opcode));
+    }
+
+    fn fault(&mut self, pc: u16, fault: Fault) {
+        self.iter_mut().for_each(|hooks| hooks.fault(pc, fault));
+    }
+}
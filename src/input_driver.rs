@@ -1,69 +1,180 @@
-use sdl2::{event::Event, Sdl, EventPump};
+use sdl2::controller::{Axis, GameController};
+use sdl2::{event::{Event, WindowEvent}, keyboard::Keycode, GameControllerSubsystem, Sdl, EventPump};
+
+use crate::display_filter::Filter;
+use crate::keymap::Keymap;
+
+// Requests for the frontend that don't belong to the chip-8 keypad
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Hotkey {
+    ToggleFullscreen,
+    ToggleFilter(Filter),
+    Redraw,
+    TogglePause,
+    FrameAdvance,
+    ToggleSlowMotion,
+    SpeedUp,
+    SpeedDown,
+    // reloads the ROM and resets the processor
+    SoftReset,
+    // starts over with fresh memory
+    HardReset,
+    // prints the call stack
+    Backtrace,
+}
+
+pub const DEFAULT_DEADZONE: i16 = 8000;
+
+// Source of held inputs that don't come from a controller
+const KEYBOARD: u32 = u32::MAX;
 
 pub struct InputDriver {
     events: EventPump,
-    pub keyboard: [bool; 16]
+    keymap: Keymap,
+    controller_subsystem: GameControllerSubsystem,
+    // open controllers, they are only reported while their handle is kept
+    controllers: Vec<GameController>,
+    // stick deflection below which an axis counts as centred
+    deadzone: i16,
+    // physical inputs currently down that are bound to a chip-8 key, with the
+    // keyboard or the id of the controller they belong to
+    held: Vec<(u32, String)>,
+    pub keyboard: [bool; 16],
+    pub hotkeys: Vec<Hotkey>,
+    // the fast-forward key is held
+    pub fast_forward: bool,
 }
 
 impl InputDriver {
-    pub fn new(sdl_context: &Sdl) -> Self{
+    pub fn new(sdl_context: &Sdl, keymap: Keymap, deadzone: i16) -> Self{
         let events = sdl_context.event_pump().unwrap();
+        // controllers connected at startup are reported as added as well
+        let controller_subsystem = sdl_context.game_controller().unwrap();
     
-        Self{events, keyboard: [false; 16]}
+        Self{
+            events,
+            keymap,
+            controller_subsystem,
+            controllers: Vec::new(),
+            deadzone,
+            held: Vec::new(),
+            keyboard: [false; 16],
+            hotkeys: Vec::new(),
+            fast_forward: false,
+        }
     }
 
     pub fn poll(&mut self) -> bool {
-        use sdl2::keyboard::Keycode;
-
-        for event in self.events.poll_iter() {
+        self.hotkeys.clear();
+        // collected first, handling controller events needs the whole driver
+        let events: Vec<Event> = self.events.poll_iter().collect();
+        for event in events {
             match event {
                 Event::Quit {..} => { return false },
-                Event::KeyDown {keycode, ..} => {
-                    match keycode.unwrap() {
-                        Keycode::Num1 => { self.keyboard[0x1] = true; },
-                        Keycode::Num2 => { self.keyboard[0x2] = true; },
-                        Keycode::Num3 => { self.keyboard[0x3] = true; },
-                        Keycode::Num4 => { self.keyboard[0xC] = true; },
-                        Keycode::Q => { self.keyboard[0x4] = true; },
-                        Keycode::W => { self.keyboard[0x5] = true; },
-                        Keycode::E => { self.keyboard[0x6] = true; },
-                        Keycode::R => { self.keyboard[0xD] = true; },
-                        Keycode::A => { self.keyboard[0x7] = true; },
-                        Keycode::S => { self.keyboard[0x8] = true; },
-                        Keycode::D => { self.keyboard[0x9] = true; },
-                        Keycode::F => { self.keyboard[0xE] = true; },
-                        Keycode::Z => { self.keyboard[0xA] = true; },
-                        Keycode::X => { self.keyboard[0x0] = true; },
-                        Keycode::C => { self.keyboard[0xB] = true; },
-                        Keycode::V => { self.keyboard[0xF] = true; },
-                        _ => {}
-                    }
+                Event::Window { win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed, .. } => {
+                    self.hotkeys.push(Hotkey::Redraw);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F11), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::ToggleFullscreen);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F1), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::ToggleFilter(Filter::Persistence));
+                }
+                Event::KeyDown {keycode: Some(Keycode::F2), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::ToggleFilter(Filter::Blending));
+                }
+                Event::KeyDown {keycode: Some(Keycode::F3), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::ToggleFilter(Filter::Scanlines));
+                }
+                Event::KeyDown {keycode: Some(Keycode::F4), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::ToggleFilter(Filter::PixelGrid));
+                }
+                Event::KeyDown {keycode: Some(Keycode::F5) | Some(Keycode::Pause), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::TogglePause);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F6), ..} => {
+                    self.hotkeys.push(Hotkey::FrameAdvance);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F7), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::ToggleSlowMotion);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F8), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::SoftReset);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F9), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::HardReset);
+                }
+                Event::KeyDown {keycode: Some(Keycode::F10), repeat: false, ..} => {
+                    self.hotkeys.push(Hotkey::Backtrace);
                 }
-                Event::KeyUp {keycode, ..} => {
-                    match keycode.unwrap() {
-                        Keycode::Num1 => { self.keyboard[0x1] = false; },
-                        Keycode::Num2 => { self.keyboard[0x2] = false; },
-                        Keycode::Num3 => { self.keyboard[0x3] = false; },
-                        Keycode::Num4 => { self.keyboard[0xC] = false; },
-                        Keycode::Q => { self.keyboard[0x4] = false; },
-                        Keycode::W => { self.keyboard[0x5] = false; },
-                        Keycode::E => { self.keyboard[0x6] = false; },
-                        Keycode::R => { self.keyboard[0xD] = false; },
-                        Keycode::A => { self.keyboard[0x7] = false; },
-                        Keycode::S => { self.keyboard[0x8] = false; },
-                        Keycode::D => { self.keyboard[0x9] = false; },
-                        Keycode::F => { self.keyboard[0xE] = false; },
-                        Keycode::Z => { self.keyboard[0xA] = false; },
-                        Keycode::X => { self.keyboard[0x0] = false; },
-                        Keycode::C => { self.keyboard[0xB] = false; },
-                        Keycode::V => { self.keyboard[0xF] = false; },
-                        _ => {}
+                Event::KeyDown {keycode: Some(Keycode::PageUp), ..} => {
+                    self.hotkeys.push(Hotkey::SpeedUp);
+                }
+                Event::KeyDown {keycode: Some(Keycode::PageDown), ..} => {
+                    self.hotkeys.push(Hotkey::SpeedDown);
+                }
+                Event::KeyDown {keycode: Some(Keycode::Tab), ..} => self.fast_forward = true,
+                Event::KeyUp {keycode: Some(Keycode::Tab), ..} => self.fast_forward = false,
+                Event::KeyDown {keycode: Some(keycode), ..} => self.press(KEYBOARD, keycode.name()),
+                Event::KeyUp {keycode: Some(keycode), ..} => self.release(KEYBOARD, &keycode.name()),
+                Event::ControllerDeviceAdded {which, ..} => {
+                    match self.controller_subsystem.open(which) {
+                        Ok(controller) => self.controllers.push(controller),
+                        Err(err) => eprintln!("warning: can't open controller {}: {}", which, err),
                     }
                 }
+                Event::ControllerDeviceRemoved {which, ..} => {
+                    self.controllers.retain(|controller| controller.instance_id() != which);
+                    self.held.retain(|(source, _)| *source != which);
+                }
+                Event::ControllerButtonDown {which, button, ..} => self.press(which, pad_button(&button.string())),
+                Event::ControllerButtonUp {which, button, ..} => self.release(which, &pad_button(&button.string())),
+                Event::ControllerAxisMotion {which, axis, value, ..} => self.move_axis(which, axis, value),
                 _ => {}
             }
         }
-        return true;
+
+        // a chip-8 key stays down as long as any of its physical inputs is held
+        self.keyboard = [false; 16];
+        for (_, name) in self.held.iter() {
+            if let Some(key) = self.keymap.key_for(name) {
+                self.keyboard[key] = true;
+            }
+        }
+        true
     }
 
-}
\ No newline at end of file
+    fn press(&mut self, source: u32, name: String) {
+        if self.keymap.key_for(&name).is_some() && !self.held.iter().any(|held| *held == (source, name.clone())) {
+            self.held.push((source, name));
+        }
+    }
+
+    fn release(&mut self, source: u32, name: &str) {
+        self.held.retain(|(held_source, held_name)| !(*held_source == source && held_name == name));
+    }
+
+    // Sticks and triggers act as a button in each direction they are pushed
+    fn move_axis(&mut self, source: u32, axis: Axis, value: i16) {
+        let positive = pad_axis(&axis.string(), '+');
+        let negative = pad_axis(&axis.string(), '-');
+        self.release(source, &positive);
+        self.release(source, &negative);
+        if value > self.deadzone {
+            self.press(source, positive);
+        } else if value < -self.deadzone {
+            self.press(source, negative);
+        }
+    }
+
+}
+
+// Controller inputs are named "pad:<button>" and "pad:<axis>+" / "pad:<axis>-"
+// after SDL's names, e.g. "pad:dpup", "pad:a" or "pad:leftx-"
+fn pad_button(button: &str) -> String {
+    format!("pad:{}", button)
+}
+
+fn pad_axis(axis: &str, direction: char) -> String {
+    format!("pad:{}{}", axis, direction)
+}
@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::Path;
+
+// Positions of the chip-8 keys on the 4x4 block presets are laid out on
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+const BLOCK_KEYS: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// Keys at the positions of BLOCK_KEYS on the left of each keyboard layout,
+// some positions have more than one name depending on how the system reports them
+const QWERTY: [&str; 16] = ["1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V"];
+const QWERTZ: [&str; 16] = ["1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Y", "X", "C", "V"];
+const AZERTY: [&str; 16] = [
+    "1|&", "2|é", "3|\"", "4|'",
+    "A", "Z", "E", "R",
+    "Q", "S", "D", "F",
+    "W", "X", "C", "V",
+];
+const DVORAK: [&str; 16] = ["1", "2", "3", "4", "'", ",", ".", "P", "A", "O", "E", "U", ";", "Q", "J", "K"];
+
+// The numeric keypad types the chip-8 keys directly, the operators stand in for A-F
+const NUMPAD: [(&str, usize); 16] = [
+    ("Keypad 0", 0x0), ("Keypad 1", 0x1), ("Keypad 2", 0x2), ("Keypad 3", 0x3),
+    ("Keypad 4", 0x4), ("Keypad 5", 0x5), ("Keypad 6", 0x6), ("Keypad 7", 0x7),
+    ("Keypad 8", 0x8), ("Keypad 9", 0x9), ("Keypad /", 0xA), ("Keypad *", 0xB),
+    ("Keypad -", 0xC), ("Keypad +", 0xD), ("Keypad Enter", 0xE), ("Keypad .", 0xF),
+];
+
+// Every preset also maps a game controller: the d-pad and the left stick to the
+// keys under W A S D (5 7 8 9), the face buttons to the keys around them
+const PAD: [(&str, usize); 16] = [
+    ("pad:dpup", 0x5), ("pad:dpleft", 0x7), ("pad:dpdown", 0x8), ("pad:dpright", 0x9),
+    ("pad:lefty-", 0x5), ("pad:leftx-", 0x7), ("pad:lefty+", 0x8), ("pad:leftx+", 0x9),
+    ("pad:a", 0x6), ("pad:b", 0x4), ("pad:x", 0xE), ("pad:y", 0xD),
+    ("pad:leftshoulder", 0x1), ("pad:rightshoulder", 0x3), ("pad:back", 0xA), ("pad:start", 0xF),
+];
+
+pub const PRESETS: [&str; 5] = ["qwerty", "qwertz", "azerty", "dvorak", "numpad"];
+
+// Maps names of physical keys (as SDL names them, e.g. "Q", "Keypad 7", "Space")
+// and controller inputs ("pad:dpup", "pad:a", "pad:leftx-") to chip-8 keys.
+// A chip-8 key can have any number of physical keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(String, usize)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset("qwerty").unwrap()
+    }
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let block = match name {
+            "qwerty" => QWERTY,
+            "qwertz" => QWERTZ,
+            "azerty" => AZERTY,
+            "dvorak" => DVORAK,
+            "numpad" => {
+                let bindings = NUMPAD.iter().chain(PAD.iter()).map(|(name, key)| (name.to_string(), *key)).collect();
+                return Some(Keymap { bindings });
+            }
+            _ => return None,
+        };
+        let mut bindings = Vec::new();
+        for (names, key) in block.iter().zip(BLOCK_KEYS) {
+            for name in names.split('|') {
+                bindings.push((name.to_string(), key));
+            }
+        }
+        bindings.extend(PAD.iter().map(|(name, key)| (name.to_string(), *key)));
+        Some(Keymap { bindings })
+    }
+
+    // A preset name or the path of a key binding file
+    pub fn from_name_or_file(name: &str) -> Result<Keymap, String> {
+        match Keymap::preset(name) {
+            Some(keymap) => Ok(keymap),
+            None if Path::new(name).is_file() => Keymap::default().with_file(Path::new(name)),
+            None => Err(format!("unknown keymap {}, expected a file or one of {}", name, PRESETS.join(", "))),
+        }
+    }
+
+    // Applies a key binding file on top of this keymap
+    pub fn with_file(self, path: &Path) -> Result<Keymap, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read key bindings {}: {}", path.display(), err))?;
+        self.with_bindings(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    // Parses key binding lines of the form
+    //   preset = azerty
+    //   5 = W, Up
+    // where every listed chip-8 key gets exactly the given physical keys. Keyboard
+    // keys replace only its keyboard keys and pad: inputs only its controller ones.
+    pub fn with_bindings(mut self, text: &str) -> Result<Keymap, String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (left, right) = line.split_once('=')
+                .ok_or(format!("line {}: expected <key> = <physical keys>", number + 1))?;
+            let (left, right) = (left.trim(), right.trim());
+
+            if left == "preset" {
+                self = Keymap::preset(right).ok_or(format!("line {}: unknown preset {}", number + 1, right))?;
+                continue;
+            }
+            let key = match usize::from_str_radix(left, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(format!("line {}: {} is not a chip-8 key (0-F)", number + 1, left)),
+            };
+            let names: Vec<&str> = right.split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+            let replaced = |bound: &str| names.is_empty() || names.iter().any(|name| is_pad(name) == is_pad(bound));
+            self.bindings.retain(|(name, bound)| *bound != key || !replaced(name));
+            for name in names {
+                self.bind(name, key);
+            }
+        }
+        Ok(self)
+    }
+
+    // Adds a physical key to a chip-8 key, a physical key drives only one chip-8 key
+    pub fn bind(&mut self, name: &str, key: usize) {
+        self.bindings.retain(|(bound, _)| !same_key(bound, name));
+        self.bindings.push((name.to_string(), key));
+    }
+
+    pub fn key_for(&self, name: &str) -> Option<usize> {
+        self.bindings.iter()
+            .find(|(bound, _)| same_key(bound, name))
+            .map(|(_, key)| *key)
+    }
+}
+
+// The keymap chosen by name (or the default) with the extra bindings and then
+// the ROM's own binding file on top
+pub fn load_keymap(name: Option<&str>, rom: &str, extra: &[(String, usize)]) -> Result<Keymap, String> {
+    let mut keymap = match name {
+        Some(name) => Keymap::from_name_or_file(name)?,
+        None => Keymap::default(),
+    };
+    for (name, key) in extra {
+        keymap.bind(name, *key);
+    }
+    let rom_bindings = rom_keymap_path(rom);
+    if rom_bindings.is_file() {
+        return keymap.with_file(&rom_bindings);
+    }
+    Ok(keymap)
+}
+
+fn is_pad(name: &str) -> bool {
+    name.starts_with("pad:")
+}
+
+// Key names are compared ignoring case, SDL names letters in upper case
+fn same_key(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+// Per ROM bindings live next to the ROM, e.g. games/pong.keys for games/pong.ch8
+pub fn rom_keymap_path(rom: &str) -> std::path::PathBuf {
+    Path::new(rom).with_extension("keys")
+}
+
+#[test]
+fn test_presets() {
+    let qwerty = Keymap::preset("qwerty").unwrap();
+    assert_eq!(qwerty.key_for("1"), Some(0x1));
+    assert_eq!(qwerty.key_for("v"), Some(0xF));
+    assert_eq!(qwerty.key_for("X"), Some(0x0));
+    assert_eq!(qwerty.key_for("P"), None);
+
+    let azerty = Keymap::preset("azerty").unwrap();
+    assert_eq!(azerty.key_for("A"), Some(0x4));
+    assert_eq!(azerty.key_for("&"), Some(0x1));
+    assert_eq!(azerty.key_for("1"), Some(0x1));
+    assert_eq!(azerty.key_for("É"), Some(0x2));
+
+    assert_eq!(Keymap::preset("qwertz").unwrap().key_for("Y"), Some(0xA));
+    assert_eq!(Keymap::preset("dvorak").unwrap().key_for("O"), Some(0x8));
+    assert_eq!(Keymap::preset("numpad").unwrap().key_for("Keypad Enter"), Some(0xE));
+    assert_eq!(Keymap::preset("numpad").unwrap().key_for("pad:dpup"), Some(0x5));
+    assert_eq!(qwerty.key_for("pad:leftx-"), Some(0x7));
+    assert!(Keymap::preset("colemak").is_none());
+    for name in PRESETS {
+        assert!(Keymap::preset(name).is_some());
+    }
+}
+
+#[test]
+fn test_bindings() {
+    let keymap = Keymap::default().with_bindings("
+        # arrows for the usual movement keys
+        preset = qwertz
+        5 = W, Up
+        8 = s, down
+    ").unwrap();
+    assert_eq!(keymap.key_for("Up"), Some(0x5));
+    assert_eq!(keymap.key_for("W"), Some(0x5));
+    assert_eq!(keymap.key_for("Down"), Some(0x8));
+    assert_eq!(keymap.key_for("Y"), Some(0xA));
+    assert_eq!(keymap.key_for("Z"), None);
+
+    let swapped = Keymap::default().with_bindings("4 = W\n5 = Q").unwrap();
+    assert_eq!(swapped.key_for("W"), Some(0x4));
+    assert_eq!(swapped.key_for("Q"), Some(0x5));
+
+    // keyboard keys leave the controller alone and the other way round
+    let rebound = Keymap::default().with_bindings("5 = Z, Up\n6 = pad:x").unwrap();
+    assert_eq!(rebound.key_for("pad:dpup"), Some(0x5));
+    assert_eq!(rebound.key_for("pad:lefty-"), Some(0x5));
+    assert_eq!(rebound.key_for("Z"), Some(0x5));
+    assert_eq!(rebound.key_for("pad:x"), Some(0x6));
+    assert_eq!(rebound.key_for("pad:a"), None);
+    assert_eq!(rebound.key_for("W"), None);
+    assert_eq!(rebound.key_for("E"), Some(0x6));
+
+    assert!(Keymap::default().with_bindings("G = Q").is_err());
+    assert!(Keymap::default().with_bindings("5 W").is_err());
+    assert!(Keymap::default().with_bindings("preset = foo").is_err());
+}
+
+#[test]
+fn test_rom_keymap_path() {
+    assert_eq!(rom_keymap_path("games/pong.ch8"), Path::new("games/pong.keys"));
+}
@@ -1,56 +1,267 @@
 extern crate sdl2;
 
+mod audio;
 mod audio_driver;
+mod cli;
+mod config;
+mod database;
 mod disassembler;
+mod display_filter;
+mod emulator;
 mod font;
+mod frame_timer;
+mod hooks;
 mod input_driver;
+mod keymap;
 mod memory;
+mod orientation;
+mod palette;
+mod platform;
 mod processor;
+mod run_control;
+mod symbols;
+mod tone;
+mod tty_frontend;
 mod video_driver;
+mod wav_writer;
 
 
+use audio::{AudioSink, SAMPLE_RATE};
 use audio_driver::AudioDriver;
-use disassembler::{NullDisassembler};
-use input_driver::InputDriver;
-use memory::MemoryBus;
-use processor::Processor;
-use std::{env, time::Duration, thread};
+use config::Config;
+use database::Database;
+use display_filter::DisplayFilters;
+use emulator::{fault_message, Emulator};
+use frame_timer::FrameTimer;
+use input_driver::{Hotkey, InputDriver};
+use keymap::load_keymap;
+use memory::{check_rom, read_rom};
+use run_control::RunControl;
+use symbols::load_symbols;
+use std::{env, fs, process};
+use tty_frontend::TtySettings;
 use video_driver::VideoDriver;
+use wav_writer::WavWriter;
 
 const CHIP_8_WIDTH: usize = 64;
 const CHIP_8_HEIGHT: usize = 32;
 
+const INSTRUCTIONS_PER_FRAME: u32 = 10;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename: &str = if args.len() != 2 {
-        "/home/hermes/cpp/Chip-8/ROMS/programs/Chip8 Picture.ch8"
-    } else {
-        &args[1]
-    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = cli::parse_args(&args).unwrap_or_else(|err| fail(&err));
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+    let rom = options.rom.clone().unwrap_or_else(|| fail("no ROM given, see --help"));
 
-    let sdl_context = sdl2::init().unwrap();
+    // defaults, then the config file's global and per ROM settings, then the command line
+    let config = Config::load(options.config.as_deref()).unwrap_or_else(|err| fail(&err));
+    let database = Database::load(options.database.as_deref()).unwrap_or_else(|err| fail(&err));
+    let rom_data = fs::read(&rom).ok();
+    let settings = config::options_for_rom(&config, database.as_ref(), rom_data.as_deref())
+        .unwrap_or_else(|err| fail(&err));
+    let options = cli::parse_args_over(settings, &args).unwrap_or_else(|err| fail(&err));
+    if let Some(title) = &options.title {
+        eprintln!("{}", title);
+    }
 
-    let mut video_driver = VideoDriver::new(&sdl_context);
-    let audio_driver = AudioDriver::new(&sdl_context);
-    let mut input = InputDriver::new(&sdl_context);
+    let keymap = load_keymap(options.keymap.as_deref(), &rom, &options.bindings).unwrap_or_else(|err| fail(&err));
 
-    let mut bus = MemoryBus::new();
+    if options.headless {
+        let mut emulator = build_emulator(&rom, &options, SAMPLE_RATE).unwrap_or_else(|err| fail(&err));
+        add_wav_sink(&mut emulator, &options);
+        if let Err(err) = run_headless(&mut emulator, options.frames) {
+            eprintln!("error: {}", err);
+            print_backtrace(&emulator);
+            // dropping the sinks finishes a recording, exit wouldn't
+            drop(emulator);
+            process::exit(1);
+        }
+        return;
+    }
 
-    bus.load_rom(filename);
+    if let Some(style) = options.tty {
+        let mut emulator = build_emulator(&rom, &options, SAMPLE_RATE).unwrap_or_else(|err| fail(&err));
+        add_wav_sink(&mut emulator, &options);
+        let settings = TtySettings { style, palette: options.palette };
+        let result = tty_frontend::run(&mut emulator, &keymap, &settings);
+        drop(emulator);
+        if let Err(err) = result {
+            fail(&err.to_string());
+        }
+        return;
+    }
 
-    let disassembler = Box::new(NullDisassembler{});
-    let mut cpu = Processor::new(bus, disassembler);
+    let sdl_context = sdl2::init().unwrap();
+    // not a sink, the loop below feeds it
+    let mut audio_driver = AudioDriver::new(&sdl_context);
+    let mut emulator = build_emulator(&rom, &options, audio_driver.sample_rate()).unwrap_or_else(|err| fail(&err));
+    add_wav_sink(&mut emulator, &options);
 
+    let mut filters = DisplayFilters::new(options.decay, options.blend_frames);
+    for filter in options.filters.iter() {
+        filters.toggle(*filter);
+    }
+    let mut video_driver = VideoDriver::new(
+        &sdl_context,
+        options.scale,
+        options.integer_scale,
+        options.fullscreen,
+        filters,
+        options.orientation,
+        options.palette,
+    );
+    let mut input = InputDriver::new(&sdl_context, keymap, options.deadzone);
+
+    let mut control = RunControl::new(options.instructions_per_frame);
+    let mut title = control.title();
+    video_driver.set_title(&title);
+
+    let mut timer = FrameTimer::new();
     while input.poll() {
-        let output = cpu.tick(&input.keyboard);
+        for hotkey in input.hotkeys.iter() {
+            match hotkey {
+                Hotkey::ToggleFullscreen => video_driver.toggle_fullscreen(),
+                Hotkey::ToggleFilter(filter) => {
+                    video_driver.toggle_filter(*filter);
+                    video_driver.draw(emulator.framebuffer());
+                }
+                Hotkey::Redraw => video_driver.redraw(),
+                Hotkey::TogglePause => control.toggle_pause(),
+                Hotkey::FrameAdvance => control.advance_frame(),
+                Hotkey::ToggleSlowMotion => control.toggle_slow_motion(),
+                Hotkey::SpeedUp => control.speed_up(),
+                Hotkey::SpeedDown => control.speed_down(),
+                Hotkey::SoftReset => {
+                    emulator.soft_reset();
+                    video_driver.draw(emulator.framebuffer());
+                }
+                Hotkey::HardReset => {
+                    emulator.reset();
+                    video_driver.draw(emulator.framebuffer());
+                }
+                Hotkey::Backtrace => print_backtrace(&emulator),
+            }
+        }
+        control.fast_forward = input.fast_forward;
+        emulator.set_instructions_per_frame(control.instructions_per_frame);
+
+        let mut vram_changed = false;
+        let mut frames_run = 0;
+        for _ in 0..control.frames_to_run() {
+            let frame = emulator.run_frame(&input.keyboard);
+            frames_run += 1;
+            vram_changed |= frame.vram_changed;
+            // a fault pauses, so the machine can be looked at
+            if !frame.faults.is_empty() {
+                for (pc, fault) in frame.faults {
+                    eprintln!("{}", fault_message(pc, fault));
+                }
+                print_backtrace(&emulator);
+                control.paused = true;
+                break;
+            }
+        }
+
+        // the sound card plays in real time, fast-forward would pile up several
+        // frames of sound per pass, so it only gets the last one
+        if frames_run > 0 {
+            audio_driver.push_samples(emulator.audio_samples());
+        }
+
+        // present at most once per frame, however many sprites were drawn
+        if vram_changed || video_driver.needs_refresh() {
+            video_driver.draw(emulator.framebuffer());
+        }
 
-        if output.vram_changed {
-            video_driver.draw(output.vram);
+        if control.title() != title {
+            title = control.title();
+            video_driver.set_title(&title);
         }
 
-        // make sound 
-        audio_driver.beep(output.beep);
+        timer.wait();
+    }
+}
+
+fn fail(err: &str) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(1);
+}
+
+// The machine the options describe with the ROM and any extra segments loaded
+fn build_emulator(rom: &str, options: &cli::Options, sample_rate: u32) -> Result<Emulator, String> {
+    let mut builder = Emulator::builder()
+        .platform(options.platform)
+        .quirks(options.quirks)
+        .seed(options.seed)
+        .instructions_per_frame(options.instructions_per_frame)
+        .font(options.font.clone())
+        .load_address(options.load_address)
+        .entry(options.entry)
+        .memory_size(options.memory_size)
+        .stack_depth(options.stack_depth)
+        .debug(options.debug)
+        .trace(options.trace)
+        .strict(options.strict)
+        .uninitialized(options.uninitialized)
+        .symbols(load_symbols(options.symbols.as_deref(), rom)?)
+        .tone(options.tone)
+        .sample_rate(sample_rate)
+        .rom(rom, read_program_file(rom)?);
+    for (file, address) in options.segments.iter() {
+        builder = builder.segment(file, read_program_file(file)?, *address);
+    }
+    builder.build()
+}
+
+// Reads a ROM or segment and warns about anything odd in it
+fn read_program_file(file: &str) -> Result<Vec<u8>, String> {
+    let data = read_rom(file).map_err(|err| err.to_string())?;
+    for warning in check_rom(&data) {
+        eprintln!("warning: {}: {}", file, warning);
+    }
+    Ok(data)
+}
 
-        thread::sleep(Duration::from_millis(10));
+// Runs the machine as fast as possible without any window or input, until the
+// frames are done, the program halts or a fault stops it
+fn run_headless(emulator: &mut Emulator, frames: Option<u64>) -> Result<(), String> {
+    let keyboard = [false; 16];
+    let mut frame = 0;
+    let mut halted = false;
+    while frames.is_none_or(|frames| frame < frames) {
+        let frame_output = emulator.run_frame(&keyboard);
+        if let Some((pc, fault)) = frame_output.faults.first() {
+            return Err(fault_message(*pc, *fault));
+        }
+        // test ROMs end on a jump to itself, a run of a given length keeps going
+        // so e.g. a recording gets the sound still playing
+        if frame_output.halted && !halted {
+            eprintln!("program halted at PC {:#05x}", emulator.cpu_state().pc);
+            if frames.is_none() {
+                return Ok(());
+            }
+            halted = true;
+        }
+        frame += 1;
+    }
+    Ok(())
+}
+
+fn print_backtrace(emulator: &Emulator) {
+    for frame in emulator.backtrace() {
+        eprintln!("  {}", frame);
+    }
+}
+
+fn add_wav_sink(emulator: &mut Emulator, options: &cli::Options) {
+    if let Some(path) = &options.wav {
+        match WavWriter::create(path, emulator.sample_rate()) {
+            Ok(writer) => emulator.add_audio_sink(Box::new(writer)),
+            Err(err) => fail(&format!("can't record to {}: {}", path, err)),
+        }
     }
 }
@@ -1,53 +1,298 @@
 
-use std::fs;
-use crate::font::FONT;
+use std::{fmt, fs, io};
+use crate::font::Font;
 
-#[derive(Debug)]
+pub const DEFAULT_MEMORY_SIZE: usize = 4096;
+// Where programs go unless the platform or the user says otherwise
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+
+// What the processor needs from memory. MemoryBus is plain RAM, instrumented
+// buses, memory mapped devices or mocks can stand in for it.
+pub trait Bus {
+    fn read_byte(&self, address: u16) -> u8;
+    fn write_byte(&mut self, address: u16, value: u8);
+    // bytes of memory, addresses go from 0 to size - 1
+    fn size(&self) -> usize;
+
+    // chip-8 is big endian
+    fn read_word(&self, address: u16) -> u16 {
+        (self.read_byte(address) as u16) << 8 | self.read_byte(address.wrapping_add(1)) as u16
+    }
+
+    #[allow(dead_code)]
+    fn write_word(&mut self, address: u16, value: u16) {
+        self.write_byte(address, (value >> 8) as u8);
+        self.write_byte(address.wrapping_add(1), value as u8);
+    }
+
+    // Whether the byte was loaded or written, reading anything else gives
+    // whatever the interpreter happened to leave there
+    fn is_initialized(&self, _address: u16) -> bool {
+        true
+    }
+
+    // Whether an access breaks the rules of a strict bus, the processor asks
+    // before every access an instruction makes
+    fn check(&self, _access: Access, _address: u16) -> Option<MemoryViolation> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    // fetching an instruction
+    Execute,
+}
+
+// Accesses a strict bus refuses, with the address accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryViolation {
+    // the font and the interpreter live below the program
+    InterpreterWrite(u16),
+    // memory past the end of the program that was never written
+    ReadPastProgram(u16),
+    ExecutePastProgram(u16),
+    // an instruction the program wrote itself
+    ExecuteData(u16),
+    // past the end of memory, usually I pointing too far for DXYN/FX33/FX55/FX65
+    OutOfBounds(u16),
+    // memory or a register (V0-VF, 16 for I) read before anything was put there
+    UninitializedRead(u16),
+    UninitializedRegister(u8),
+}
+
+// What to do when a program reads memory or registers it never set, which
+// interpreters initialise differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UninitializedCheck {
+    #[default]
+    Off,
+    Warn,
+    // trap like strict mode does
+    Break,
+}
+
+impl UninitializedCheck {
+    pub fn from_name(name: &str) -> Option<UninitializedCheck> {
+        match name {
+            "off" => Some(UninitializedCheck::Off),
+            "warn" => Some(UninitializedCheck::Warn),
+            "break" => Some(UninitializedCheck::Break),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MemoryViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryViolation::InterpreterWrite(address) => write!(f, "write to {:#05x} in the interpreter area", address),
+            MemoryViolation::ReadPastProgram(address) => write!(f, "read of unwritten memory at {:#05x} past the program", address),
+            MemoryViolation::ExecutePastProgram(address) => write!(f, "execution at {:#05x} past the program", address),
+            MemoryViolation::ExecuteData(address) => write!(f, "execution of data the program wrote at {:#05x}", address),
+            MemoryViolation::OutOfBounds(address) => write!(f, "access to {:#06x} outside of memory", address),
+            MemoryViolation::UninitializedRead(address) => write!(f, "read of uninitialized memory at {:#05x}", address),
+            MemoryViolation::UninitializedRegister(16) => write!(f, "read of I before it was set"),
+            MemoryViolation::UninitializedRegister(register) => write!(f, "read of V{:X} before it was set", register),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct MemoryBus{
-    rom: [u8; 4096],
+    rom: Vec<u8>,
+    // bytes loaded from the ROM or font, or written by the program
+    initialized: Vec<bool>,
+    // bytes the program wrote since they were loaded
+    written: Vec<bool>,
+    // end of the last byte loaded, the program and its data are below it
+    program_end: usize,
+    // strict mode refuses writes below this address
+    protected_end: Option<u16>,
+}
+
+// Why a ROM could not be loaded
+#[derive(Debug, PartialEq, Eq)]
+pub enum RomError {
+    NotFound(String),
+    Unreadable(String, String),
+    Empty(String),
+    TooLarge { name: String, size: usize, space: usize, address: u16 },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::NotFound(name) => write!(f, "ROM {} not found", name),
+            RomError::Unreadable(name, err) => write!(f, "can't read ROM {}: {}", name, err),
+            RomError::Empty(name) => write!(f, "ROM {} is empty", name),
+            RomError::TooLarge { name, size, space, address } => write!(f,
+                "ROM {} is {} bytes, only {} fit at {:#05x} in this platform's memory", name, size, space, address),
+        }
+    }
+}
+
+// Things about a ROM that load fine but hint at the wrong file
+#[derive(Debug, PartialEq, Eq)]
+pub enum RomWarning {
+    // instructions are two bytes, although some programs do end on a data byte
+    OddLength(usize),
+    // the file starts like a well known non chip-8 format
+    ForeignFormat(&'static str),
+    // every byte is the same, e.g. an erased EPROM dump
+    Blank(u8),
 }
 
-const MAX_ROM_SIZE: usize = 3584;
+impl fmt::Display for RomWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomWarning::OddLength(size) => write!(f, "ROM has an odd length ({} bytes)", size),
+            RomWarning::ForeignFormat(format) => write!(f, "ROM looks like a {} file, not a chip-8 program", format),
+            RomWarning::Blank(byte) => write!(f, "ROM contains nothing but {:#04x}", byte),
+        }
+    }
+}
 
 impl MemoryBus{
+    // The original 4K with the default font, the frontends size memory for the
+    // platform and pick the font instead
+    #[allow(dead_code)]
     pub fn new() -> Self {
-        let mut rom= [0;4096];
-        for (i, data) in FONT.into_iter().enumerate() {
-            rom[i] = data;
+        let mut bus = MemoryBus::with_size(DEFAULT_MEMORY_SIZE);
+        bus.load_font(&Font::default()).unwrap();
+        bus
+    }
+
+    // Some platforms have more than the original 4K, e.g. XO-CHIP with 64K
+    pub fn with_size(size: usize) -> Self {
+        MemoryBus {
+            rom: vec![0; size],
+            initialized: vec![false; size],
+            written: vec![false; size],
+            program_end: 0,
+            protected_end: None,
+        }
+    }
+
+    // Reports bad accesses instead of making them, writes below the given
+    // address are bad as well as the accesses MemoryViolation lists
+    pub fn set_strict(&mut self, protected_end: u16) {
+        self.protected_end = Some(protected_end);
+    }
+
+    // Copies the small and then the big font to the font's address
+    pub fn load_font(&mut self, font: &Font) -> Result<(), String> {
+        let start = font.address as usize;
+        if start + font.size() > self.rom.len() {
+            return Err(format!("a {} byte font does not fit at {:#05x}", font.size(), font.address));
+        }
+        for (i, data) in font.small.iter().chain(font.big.iter()).enumerate() {
+            self.rom[start + i] = *data;
+            self.initialized[start + i] = true;
         }
+        Ok(())
+    }
 
-        MemoryBus { rom}
+    // Copies the ROM into memory at the given address
+    #[allow(dead_code)]
+    pub fn load_rom(&mut self, filename: &str, address: u16) -> Result<Vec<RomWarning>, RomError> {
+        let rom_data = read_rom(filename)?;
+        self.load_bytes(filename, &rom_data, address)
     }
 
-    pub fn load_rom(&mut self, filename: &str) {
-        let rom_data = load_rom(filename);
-        let start_offset = 512;
-        for (i, data) in rom_data.into_iter().enumerate() {
-            if i > MAX_ROM_SIZE { break; }
-            self.rom[start_offset + i] = data;
+    pub fn load_bytes(&mut self, name: &str, data: &[u8], address: u16) -> Result<Vec<RomWarning>, RomError> {
+        if data.is_empty() {
+            return Err(RomError::Empty(name.to_string()));
+        }
+        let start = address as usize;
+        let space = self.rom.len().saturating_sub(start);
+        if data.len() > space {
+            return Err(RomError::TooLarge { name: name.to_string(), size: data.len(), space, address });
         }
+        self.rom[start..start + data.len()].copy_from_slice(data);
+        self.initialized[start..start + data.len()].fill(true);
+        self.written[start..start + data.len()].fill(false);
+        self.program_end = self.program_end.max(start + data.len());
+        Ok(check_rom(data))
+    }
+
+    // Addresses past the end wrap around to the start, like they do on the VIP
+    // when I is left near the top of memory. A strict bus reports them instead.
+    fn index(&self, address: u16) -> usize {
+        address as usize % self.rom.len()
     }
+}
 
-    pub fn read_byte(&self, addr: u16) -> u8 {
-        assert!(addr < 4096);
-        *self.rom.get(addr as usize).unwrap()
+impl Bus for MemoryBus {
+    fn read_byte(&self, addr: u16) -> u8 {
+        self.rom[self.index(addr)]
     }
 
-    pub fn read_word(&self, addr: u16) -> u16{
-        let hi = *self.rom.get(addr as usize).unwrap() as u16;
-        let lo = *self.rom.get(addr as usize + 1).unwrap() as u16;
-        ((hi << 8) | lo) as u16
+    fn write_byte(&mut self, addr: u16, val: u8) {
+        let index = self.index(addr);
+        self.rom[index] = val;
+        self.initialized[index] = true;
+        self.written[index] = true;
     }
 
-    pub fn write(&mut self, addr: u16, val: u8) {
-        self.rom[addr as usize] = val;
+    fn is_initialized(&self, address: u16) -> bool {
+        self.initialized[self.index(address)]
+    }
+
+    fn size(&self) -> usize {
+        self.rom.len()
+    }
+
+    fn check(&self, access: Access, address: u16) -> Option<MemoryViolation> {
+        let protected_end = self.protected_end?;
+        let index = address as usize;
+        let last = match access {
+            Access::Execute => index + 1,
+            _ => index,
+        };
+        if last >= self.rom.len() {
+            return Some(MemoryViolation::OutOfBounds(address));
+        }
+        match access {
+            Access::Write if address < protected_end => Some(MemoryViolation::InterpreterWrite(address)),
+            Access::Read if index >= self.program_end && !self.written[index] => Some(MemoryViolation::ReadPastProgram(address)),
+            Access::Execute if self.written[index] || self.written[index + 1] => Some(MemoryViolation::ExecuteData(address)),
+            Access::Execute if index >= self.program_end => Some(MemoryViolation::ExecutePastProgram(address)),
+            _ => None,
+        }
     }
+}
 
+pub fn read_rom(filename: &str) -> Result<Vec<u8>, RomError> {
+    fs::read(filename).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => RomError::NotFound(filename.to_string()),
+        _ => RomError::Unreadable(filename.to_string(), err.to_string()),
+    })
 }
 
-pub fn load_rom(filename: &str) -> Vec<u8> {
-    
-    fs::read(filename).unwrap()
+// File signatures of formats people mistake for ROMs
+const FOREIGN_FORMATS: [(&[u8], &str); 5] = [
+    (b"PK\x03\x04", "zip"),
+    (b"\x1f\x8b", "gzip"),
+    (b"\x7fELF", "ELF"),
+    (b"\x89PNG", "PNG"),
+    (b"<!DOCTYPE", "HTML"),
+];
+
+pub fn check_rom(data: &[u8]) -> Vec<RomWarning> {
+    let mut warnings = Vec::new();
+    if data.len() % 2 == 1 {
+        warnings.push(RomWarning::OddLength(data.len()));
+    }
+    if let Some((_, format)) = FOREIGN_FORMATS.iter().find(|(magic, _)| data.starts_with(magic)) {
+        warnings.push(RomWarning::ForeignFormat(format));
+    }
+    if data.len() > 1 && data.iter().all(|&byte| byte == data[0]) {
+        warnings.push(RomWarning::Blank(data[0]));
+    }
+    warnings
 }
 
 
@@ -65,13 +310,25 @@ fn test_read() {
     let bus: MemoryBus = MemoryBus::new();
 
     let read_value = bus.read_byte(0x00);
-    assert_eq!(read_value, FONT[0]);
+    assert_eq!(read_value, crate::font::FONT[0]);
+}
+
+#[test]
+fn test_load_font() {
+    let mut bus = MemoryBus::with_size(DEFAULT_MEMORY_SIZE);
+    let font = Font { address: 0x50, ..Font::preset("vip").unwrap() };
+    bus.load_font(&font).unwrap();
+    assert_eq!(bus.read_byte(0x00), 0);
+    assert_eq!(bus.read_byte(0x50 + 5), 0x60);
+    // the big font right after the small one
+    assert_eq!(bus.read_byte(0x50 + 80), 0x3C);
+    assert!(bus.load_font(&Font { address: 0xFF0, ..Font::default() }).is_err());
 }
 
 #[test]
 fn test_write() {
     let mut bus  = MemoryBus::new();
-    bus.write(0x200, 32);
+    bus.write_byte(0x200, 32);
     let val_read = bus.read_byte(0x200);
     assert_eq!(val_read, 32);
 }
@@ -79,8 +336,91 @@ fn test_write() {
 #[test]
 fn test_load_rom() {
     let mut bus = MemoryBus::new();
-    let test_program = "/home/hermes/cpp/Chip-8/ROMS/programs/BMP Viewer - Hello (C8 example) [Hap, 2005].ch8";
-    bus.load_rom(test_program);
+    let test_program = std::env::temp_dir().join("chip_8_rs_test_load_rom.ch8");
+    fs::write(&test_program, [0x12, 0x00, 0x00, 0xE0]).unwrap();
+    let warnings = bus.load_rom(test_program.to_str().unwrap(), 0x200).unwrap();
+    fs::remove_file(&test_program).unwrap();
 
+    assert!(warnings.is_empty());
     assert_eq!(bus.rom[512], 0x12);
-}
\ No newline at end of file
+    assert_eq!(bus.rom[515], 0xE0);
+}
+
+#[test]
+fn test_load_rom_errors() {
+    let mut bus = MemoryBus::new();
+    assert_eq!(bus.load_rom("/nonexistent/game.ch8", 0x200), Err(RomError::NotFound("/nonexistent/game.ch8".to_string())));
+    assert_eq!(bus.load_bytes("empty", &[], 0x200), Err(RomError::Empty("empty".to_string())));
+
+    // exactly 3584 bytes fit after 0x200, one more doesn't
+    assert!(bus.load_bytes("full", &[0x12; 3584], 0x200).is_ok());
+    assert_eq!(
+        bus.load_bytes("big", &[0x12; 3585], 0x200),
+        Err(RomError::TooLarge { name: "big".to_string(), size: 3585, space: 3584, address: 0x200 }),
+    );
+    assert!(MemoryBus::with_size(0x10000).load_bytes("big", &[0x12; 3585], 0x200).is_ok());
+}
+
+#[test]
+fn test_rom_warnings() {
+    assert_eq!(check_rom(&[0x00, 0xE0, 0x12]), vec![RomWarning::OddLength(3)]);
+    assert_eq!(check_rom(b"PK\x03\x04data"), vec![RomWarning::ForeignFormat("zip")]);
+    assert_eq!(check_rom(&[0xFF; 64]), vec![RomWarning::Blank(0xFF)]);
+}
+
+#[test]
+fn test_bus_words() {
+    let mut bus = MemoryBus::new();
+    bus.write_word(0x300, 0x12AB);
+    assert_eq!(bus.read_byte(0x300), 0x12);
+    assert_eq!(bus.read_word(0x300), 0x12AB);
+    assert_eq!(bus.size(), DEFAULT_MEMORY_SIZE);
+}
+
+#[test]
+fn test_addresses_past_the_end() {
+    let mut bus = MemoryBus::with_size(4096);
+    bus.write_byte(0x1001, 0xAB);
+    assert_eq!(bus.read_byte(0x0001), 0xAB);
+    assert_eq!(bus.read_byte(0xF001), 0xAB);
+    assert!(bus.is_initialized(0x1001));
+    assert_eq!(bus.read_word(0x0FFF), 0x0000);
+}
+
+#[test]
+fn test_strict_checks() {
+    let mut bus = MemoryBus::new();
+    assert_eq!(bus.check(Access::Write, 0x050), None);
+    bus.set_strict(0x200);
+    bus.load_bytes("test", &[0x12, 0x00, 0x00, 0x00], 0x200).unwrap();
+    assert_eq!(bus.check(Access::Write, 0x050), Some(MemoryViolation::InterpreterWrite(0x050)));
+    assert_eq!(bus.check(Access::Write, 0x300), None);
+    assert_eq!(bus.check(Access::Read, 0x203), None);
+    assert_eq!(bus.check(Access::Read, 0x300), Some(MemoryViolation::ReadPastProgram(0x300)));
+    assert_eq!(bus.check(Access::Execute, 0x204), Some(MemoryViolation::ExecutePastProgram(0x204)));
+    assert_eq!(bus.check(Access::Read, 0x1000), Some(MemoryViolation::OutOfBounds(0x1000)));
+    assert_eq!(bus.check(Access::Execute, 0xFFF), Some(MemoryViolation::OutOfBounds(0xFFF)));
+
+    bus.write_byte(0x300, 0x12);
+    bus.write_byte(0x202, 0x12);
+    assert_eq!(bus.check(Access::Read, 0x300), None);
+    assert_eq!(bus.check(Access::Execute, 0x202), Some(MemoryViolation::ExecuteData(0x202)));
+    // loading the program again makes it code again
+    bus.load_bytes("test", &[0x12, 0x00, 0x00, 0x00], 0x200).unwrap();
+    assert_eq!(bus.check(Access::Execute, 0x202), None);
+}
+
+#[test]
+fn test_initialized() {
+    let mut bus = MemoryBus::new();
+    assert!(bus.is_initialized(0x000));
+    assert!(!bus.is_initialized(0x200));
+    bus.load_bytes("test", &[0x12, 0x00], 0x200).unwrap();
+    assert!(bus.is_initialized(0x201));
+    assert!(!bus.is_initialized(0x202));
+    bus.write_byte(0x300, 0);
+    assert!(bus.is_initialized(0x300));
+    // past the end is the font at the start again
+    assert!(bus.is_initialized(0x1000));
+    assert!(!bus.is_initialized(0x1202));
+}
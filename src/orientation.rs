@@ -0,0 +1,93 @@
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u32) -> Option<Rotation> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(&self) -> u32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Clockwise180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+}
+
+// How the framebuffer is turned before it is shown, flips are applied first
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl Orientation {
+    // Parses "h", "v" or "hv" as accepted by --flip
+    pub fn set_flip(&mut self, flip: &str) -> Result<(), String> {
+        self.flip_horizontal = false;
+        self.flip_vertical = false;
+        for axis in flip.chars() {
+            match axis {
+                'h' => self.flip_horizontal = true,
+                'v' => self.flip_vertical = true,
+                _ => return Err(format!("invalid flip: {}", flip)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_sideways(&self) -> bool {
+        matches!(self.rotation, Rotation::Clockwise90 | Rotation::Clockwise270)
+    }
+
+    // Size of the picture on screen for a framebuffer of width x height
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.is_sideways() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+}
+
+#[test]
+fn test_rotation_degrees() {
+    assert_eq!(Rotation::from_degrees(270), Some(Rotation::Clockwise270));
+    assert_eq!(Rotation::from_degrees(45), None);
+    assert_eq!(Rotation::Clockwise90.degrees(), 90);
+}
+
+#[test]
+fn test_output_size() {
+    let mut orientation = Orientation::default();
+    assert_eq!(orientation.output_size(64, 32), (64, 32));
+    orientation.rotation = Rotation::Clockwise90;
+    assert_eq!(orientation.output_size(64, 32), (32, 64));
+    orientation.rotation = Rotation::Clockwise180;
+    assert_eq!(orientation.output_size(64, 32), (64, 32));
+}
+
+#[test]
+fn test_set_flip() {
+    let mut orientation = Orientation::default();
+    orientation.set_flip("hv").unwrap();
+    assert!(orientation.flip_horizontal && orientation.flip_vertical);
+    orientation.set_flip("v").unwrap();
+    assert!(!orientation.flip_horizontal && orientation.flip_vertical);
+    assert!(orientation.set_flip("x").is_err());
+}
@@ -0,0 +1,72 @@
+// Colours of lit and unlit pixels
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Palette {
+    pub on: (u8, u8, u8),
+    pub off: (u8, u8, u8),
+}
+
+pub const PALETTES: [&str; 4] = ["green", "white", "amber", "lcd"];
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::preset("green").unwrap()
+    }
+}
+
+impl Palette {
+    pub fn preset(name: &str) -> Option<Palette> {
+        let (on, off) = match name {
+            "green" => ((0, 225, 0), (0, 0, 0)),
+            "white" => ((255, 255, 255), (0, 0, 0)),
+            "amber" => ((255, 176, 0), (0, 0, 0)),
+            "lcd" => ((15, 56, 15), (155, 188, 15)),
+            _ => return None,
+        };
+        Some(Palette { on, off })
+    }
+
+    // A preset name or two colours "RRGGBB,RRGGBB" for lit and unlit pixels
+    pub fn from_spec(spec: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::preset(spec) {
+            return Ok(palette);
+        }
+        let invalid = || format!("invalid palette {}, expected RRGGBB,RRGGBB or one of {}", spec, PALETTES.join(", "));
+        let (on, off) = spec.split_once(',').ok_or_else(invalid)?;
+        let on = parse_color(on).ok_or_else(invalid)?;
+        let off = parse_color(off).ok_or_else(invalid)?;
+        Ok(Palette { on, off })
+    }
+
+    // The colour of a pixel lit with the given intensity between 0.0 and 1.0
+    pub fn mix(&self, intensity: f32) -> (u8, u8, u8) {
+        let mix = |off: u8, on: u8| (off as f32 + (on as f32 - off as f32) * intensity) as u8;
+        (mix(self.off.0, self.on.0), mix(self.off.1, self.on.1), mix(self.off.2, self.on.2))
+    }
+}
+
+fn parse_color(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+#[test]
+fn test_palette_from_spec() {
+    assert_eq!(Palette::from_spec("green").unwrap(), Palette::default());
+    let custom = Palette::from_spec("#FF8000,102030").unwrap();
+    assert_eq!(custom.on, (255, 128, 0));
+    assert_eq!(custom.off, (16, 32, 48));
+    assert!(Palette::from_spec("purple").is_err());
+    assert!(Palette::from_spec("FF8000").is_err());
+    assert!(Palette::from_spec("FF80,000000").is_err());
+}
+
+#[test]
+fn test_palette_mix() {
+    let palette = Palette::from_spec("lcd").unwrap();
+    assert_eq!(palette.mix(0.0), palette.off);
+    assert_eq!(palette.mix(1.0), palette.on);
+}
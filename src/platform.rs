@@ -0,0 +1,170 @@
+use crate::memory::DEFAULT_MEMORY_SIZE;
+
+// Machines chip-8 programs were written for, they disagree on a few instructions
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Platform {
+    // the original interpreter on the COSMAC VIP
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+pub const PLATFORMS: [&str; 3] = ["chip8", "schip", "xochip"];
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" | "vip" => Some(Platform::Chip8),
+            "schip" | "superchip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    // The most memory the platform can address, 12 bit addresses reach 4K
+    pub fn max_memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => DEFAULT_MEMORY_SIZE,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    // The requested memory size if the platform can use that much, all of it otherwise
+    pub fn memory_size(&self, requested: Option<usize>) -> Result<usize, String> {
+        match requested {
+            None => Ok(self.max_memory_size()),
+            Some(size) if (0x1000..=self.max_memory_size()).contains(&size) => Ok(size),
+            Some(size) => Err(format!("{} bytes of memory is not possible on this platform (4096-{})",
+                size, self.max_memory_size())),
+        }
+    }
+
+    // The VIP interpreter keeps 12 return addresses, SCHIP and Octo 16
+    pub fn stack_depth(&self) -> StackDepth {
+        match self {
+            Platform::Chip8 => StackDepth::Frames(12),
+            Platform::SuperChip | Platform::XoChip => StackDepth::Frames(16),
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                shift_vx: false,
+                memory_increment: true,
+                jump_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+            },
+            Platform::SuperChip => Quirks {
+                shift_vx: true,
+                memory_increment: false,
+                jump_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+            },
+            Platform::XoChip => Quirks {
+                shift_vx: false,
+                memory_increment: true,
+                jump_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+            },
+        }
+    }
+}
+
+// How deep subroutine calls can nest before CALL overflows the stack
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StackDepth {
+    Frames(usize),
+    Unlimited,
+}
+
+impl StackDepth {
+    pub fn from_name(name: &str) -> Option<StackDepth> {
+        match name {
+            "unlimited" => Some(StackDepth::Unlimited),
+            _ => name.parse().ok().filter(|&depth| depth > 0).map(StackDepth::Frames),
+        }
+    }
+}
+
+// Instruction behaviours that differ between platforms
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of shifting VY into VX
+    pub shift_vx: bool,
+    // FX55/FX65 leave I pointing past the last register stored or loaded
+    pub memory_increment: bool,
+    // BNNN is BXNN, jumping to XNN + VX instead of NNN + V0
+    pub jump_vx: bool,
+    // 8XY1/8XY2/8XY3 clear VF
+    pub vf_reset: bool,
+    // sprites are cut off at the edges of the screen instead of wrapping around
+    pub clip_sprites: bool,
+}
+
+pub const QUIRKS: [&str; 5] = ["shift", "memory", "jump", "vfreset", "clip"];
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Platform::default().quirks()
+    }
+}
+
+impl Quirks {
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let quirk = match name {
+            "shift" => &mut self.shift_vx,
+            "memory" => &mut self.memory_increment,
+            "jump" => &mut self.jump_vx,
+            "vfreset" => &mut self.vf_reset,
+            "clip" => &mut self.clip_sprites,
+            _ => return Err(format!("unknown quirk {}, expected one of {}", name, QUIRKS.join(", "))),
+        };
+        *quirk = enabled;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_platforms() {
+    for name in PLATFORMS {
+        assert!(Platform::from_name(name).is_some());
+    }
+    assert_eq!(Platform::from_name("vip"), Some(Platform::Chip8));
+    assert_eq!(Platform::from_name("megachip"), None);
+    assert!(Platform::SuperChip.quirks().jump_vx);
+    assert!(!Platform::XoChip.quirks().clip_sprites);
+}
+
+#[test]
+fn test_memory_size() {
+    assert_eq!(Platform::Chip8.memory_size(None), Ok(4096));
+    assert_eq!(Platform::XoChip.memory_size(None), Ok(65536));
+    assert_eq!(Platform::XoChip.memory_size(Some(16384)), Ok(16384));
+    assert!(Platform::Chip8.memory_size(Some(16384)).is_err());
+    assert!(Platform::XoChip.memory_size(Some(1024)).is_err());
+}
+
+#[test]
+fn test_set_quirk() {
+    let mut quirks = Platform::Chip8.quirks();
+    quirks.set("shift", true).unwrap();
+    quirks.set("clip", false).unwrap();
+    assert!(quirks.shift_vx);
+    assert!(!quirks.clip_sprites);
+    assert!(quirks.set("wobble", true).is_err());
+}
+
+#[test]
+fn test_stack_depth() {
+    assert_eq!(Platform::Chip8.stack_depth(), StackDepth::Frames(12));
+    assert_eq!(Platform::SuperChip.stack_depth(), StackDepth::Frames(16));
+    assert_eq!(StackDepth::from_name("unlimited"), Some(StackDepth::Unlimited));
+    assert_eq!(StackDepth::from_name("32"), Some(StackDepth::Frames(32)));
+    assert_eq!(StackDepth::from_name("0"), None);
+    assert_eq!(StackDepth::from_name("deep"), None);
+}
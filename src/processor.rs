@@ -1,16 +1,29 @@
 #![allow(dead_code, unused)]
-use rand::Rng;
-use std::{fmt::Display, ops::{Shr, BitAnd, Shl, BitOr, BitAndAssign, BitOrAssign, BitXorAssign}};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{collections::BTreeSet, fmt::Display, ops::{Shr, BitAnd, Shl, BitOr, BitAndAssign, BitOrAssign, BitXorAssign}};
 
-use crate::{memory::MemoryBus, disassembler::Disassembler, CHIP_8_WIDTH, CHIP_8_HEIGHT};
+use crate::{memory::{Access, Bus, MemoryBus, MemoryViolation, UninitializedCheck}, hooks::{Hooks, Timer}, font::{big_glyph, small_glyph, DEFAULT_FONT_ADDRESS}, platform::{Platform, Quirks, StackDepth}, symbols::Symbols, tone::{AudioPattern, DEFAULT_PITCH}, CHIP_8_WIDTH, CHIP_8_HEIGHT};
 
-const CHIP_8_STACK_SIZE: usize = 16;
 const CHIP_8_REGISTERS: usize = 16;
 
 pub struct ProcessorOutput<'a> {
     pub vram: &'a [[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT],
     pub vram_changed: bool,
-    pub beep: bool
+    pub beep: bool,
+    // Value the sound timer was loaded with by this instruction
+    pub sound_timer_set: Option<u8>,
+    // The XO-CHIP audio pattern or pitch was changed by this instruction
+    pub audio_pattern_changed: bool,
+    // The instruction started a loop nothing can end before the timers tick
+    pub busy_wait: Option<BusyWait>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusyWait {
+    // a jump to itself, which test ROMs use to stop
+    Halted,
+    // FX07 and a skip on VX with a jump back, waiting for the delay timer
+    DelayTimer,
 }
 
 enum ProcessorAction {
@@ -19,71 +32,385 @@ enum ProcessorAction {
     Jump(u16)
 }
 
-pub struct Processor{
+// Something the program did that stops the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // an access strict mode or the uninitialized check trapped
+    Memory(MemoryViolation),
+    // a CALL with the stack full at this many frames
+    StackOverflow { subroutine: u16, depth: usize },
+    // a RET with nothing on the stack
+    StackUnderflow,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::Memory(violation) => write!(f, "{}", violation),
+            Fault::StackOverflow { subroutine, depth } =>
+                write!(f, "stack overflow, CALL {:#05x} with {} frames on the stack", subroutine, depth),
+            Fault::StackUnderflow => write!(f, "stack underflow, RET with nothing on the stack"),
+        }
+    }
+}
+
+// A subroutine call on the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFrame {
+    // the address of the CALL
+    pub call: u16,
+    // where it went
+    pub subroutine: u16,
+}
+
+impl StackFrame {
+    pub fn return_address(&self) -> u16 {
+        self.call.wrapping_add(2)
+    }
+}
+
+// Everything that changes while a program runs, memory included, to go back to later
+#[derive(Clone)]
+pub struct Snapshot<B = MemoryBus> {
+    pc: u16,
+    v: [u8; CHIP_8_REGISTERS],
+    reg_i: u16,
+    sound_timer: u8,
+    delay_timer: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    stack: Vec<StackFrame>,
+    vram: [[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT],
+    wait_for_key: bool,
+    saved_key_state: [bool; 16],
+    rng: StdRng,
+    bus: B,
+}
+
+impl<B> Snapshot<B> {
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+}
+
+pub struct Processor<B: Bus = MemoryBus> {
     // Registers
     pc: u16,
-    sp: usize,
     v: [u8;16],
     reg_i: u16,
     sound_timer: u8,
     delay_timer: u8,
     vram_changed: bool,
-    // Area for the stack
-    stack: [u16; 16],
+    sound_timer_set: Option<u8>,
+    // XO-CHIP audio, the buzzer plays the pattern once one was loaded
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    audio_pattern_changed: bool,
+    busy_wait: Option<BusyWait>,
+    // Subroutines called and not returned from, the most recent last
+    stack: Vec<StackFrame>,
+    stack_depth: StackDepth,
     vram: [[u8; 64]; 32],
     // Keyboard specific
     wait_for_key: bool,
     saved_key_state: [bool; 16],
+    // Platform differences
+    quirks: Quirks,
+    rng: StdRng,
+    // Where the program starts, also after a reset
+    start_address: u16,
+    // Where the font is in memory, FX29 and FX30 point into it
+    font_address: u16,
+    // Print every instruction with the machine state before it runs
+    trace: bool,
+    // Faults with the PC of the instruction, until taken
+    faults: Vec<(u16, Fault)>,
+    // Reads of memory and registers nothing was put in, each is reported once
+    uninitialized: UninitializedCheck,
+    // V0-VF, then I in bit 16
+    initialized_registers: u32,
+    reported_addresses: BTreeSet<u16>,
     // Memory bus
-    bus: MemoryBus,
-    // Disassembler object
-    disassembler: Box<dyn Disassembler>,
+    bus: B,
+    // Called as instructions run, e.g. the disassembler
+    hooks: Box<dyn Hooks>,
 }
 
-impl Display for Processor {
+// A copy of the machine's registers, stack and screen for debuggers, cheat tools
+// and test harnesses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuState {
+    pub pc: u16,
+    pub sp: usize,
+    pub v: [u8; CHIP_8_REGISTERS],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    // addresses of the calls, the most recent last, RET continues after them
+    pub stack: Vec<u16>,
+    pub vram: [[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT],
+}
+
+impl<B: Bus> Display for Processor<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "v: {:?}\npc: {}\nsp: {}\nI: {}\nST: {}\nDT: {}",
-            self.v, self.pc, self.sp, self.reg_i, self.sound_timer, self.delay_timer)
+            self.v, self.pc, self.stack.len(), self.reg_i, self.sound_timer, self.delay_timer)
     }
 }
 
-impl Processor {
-    pub fn new(membus: MemoryBus, dis: Box<dyn Disassembler>) -> Self {
+impl<B: Bus> Processor<B> {
+    pub fn new(membus: B, hooks: Box<dyn Hooks>) -> Self {
         Processor { 
             pc: 0x200, 
-            sp: 0, 
-            stack: [0; CHIP_8_STACK_SIZE],
+            stack: Vec::new(),
+            stack_depth: Platform::default().stack_depth(),
             v: [0; CHIP_8_REGISTERS],
             reg_i: 0, 
             sound_timer: 0,
             delay_timer: 0,
             vram_changed: false,
+            sound_timer_set: None,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            audio_pattern_changed: false,
+            busy_wait: None,
             vram: [[0 ; CHIP_8_WIDTH]; CHIP_8_HEIGHT],
             wait_for_key: false,
             saved_key_state: [false; 16],
+            quirks: Quirks::default(),
+            rng: StdRng::from_entropy(),
+            start_address: 0x200,
+            font_address: DEFAULT_FONT_ADDRESS,
+            trace: false,
+            faults: Vec::new(),
+            uninitialized: UninitializedCheck::Off,
+            initialized_registers: 0,
+            reported_addresses: BTreeSet::new(),
             bus: membus,
-            disassembler: dis,
+            hooks,
         } 
     }
 
-    pub fn tick(&mut self, keyboard: &[bool; 16]) -> ProcessorOutput {
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    // Makes CXNN produce the same numbers on every run
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn with_start_address(mut self, address: u16) -> Self {
+        self.start_address = address;
+        self.pc = address;
+        self
+    }
+
+    pub fn with_stack_depth(mut self, depth: StackDepth) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    pub fn with_font_address(mut self, address: u16) -> Self {
+        self.font_address = address;
+        self
+    }
+
+    pub fn with_uninitialized_check(mut self, check: UninitializedCheck) -> Self {
+        self.uninitialized = check;
+        self
+    }
+
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    // Puts the machine back into its power on state, memory is left alone
+    pub fn reset(&mut self) {
+        self.pc = self.start_address;
+        self.stack.clear();
+        self.v = [0; CHIP_8_REGISTERS];
+        self.reg_i = 0;
+        self.sound_timer = 0;
+        self.delay_timer = 0;
         self.vram_changed = false;
-        // TODO Check keyboard
+        self.sound_timer_set = None;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.audio_pattern_changed = false;
+        self.busy_wait = None;
+        self.vram = [[0; CHIP_8_WIDTH]; CHIP_8_HEIGHT];
+        self.wait_for_key = false;
+        self.saved_key_state = [false; 16];
+        self.faults.clear();
+        self.initialized_registers = 0;
+        self.reported_addresses.clear();
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            pc: self.pc,
+            sp: self.stack.len(),
+            v: self.v,
+            i: self.reg_i,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            stack: self.stack.iter().map(|frame| frame.call).collect(),
+            vram: self.vram,
+        }
+    }
+
+    pub fn set_register(&mut self, x: usize, value: u8) -> Result<(), String> {
+        let register = self.v.get_mut(x).ok_or(format!("there is no register v{:X}", x))?;
+        *register = value;
+        Ok(())
+    }
+
+    pub fn set_i(&mut self, value: u16) {
+        self.reg_i = value;
+    }
+
+    pub fn set_pc(&mut self, address: u16) -> Result<(), String> {
+        if address as usize >= self.bus.size() {
+            return Err(format!("{:#06x} is outside of memory", address));
+        }
+        self.pc = address;
+        Ok(())
+    }
+
+    pub fn set_timers(&mut self, delay_timer: u8, sound_timer: u8) {
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+    }
+
+    // Pushes a call as if the code at PC had been called from the address
+    pub fn push_stack(&mut self, address: u16) -> Result<(), String> {
+        if self.stack_full() {
+            return Err("the stack is full".to_string());
+        }
+        self.stack.push(StackFrame { call: address, subroutine: self.pc });
+        Ok(())
+    }
 
-        // TODO decrement timers
+    pub fn pop_stack(&mut self) -> Option<u16> {
+        self.stack.pop().map(|frame| frame.call)
+    }
+
+    pub fn stack_frames(&self) -> &[StackFrame] {
+        &self.stack
+    }
+
+    // Where the machine is and how it got there, the innermost call first, e.g.
+    //   #0 0x2a6 in draw_paddle+0x6
+    //   #1 0x212 in main+0x12
+    pub fn backtrace(&self, symbols: &Symbols) -> Vec<String> {
+        let function = |address: u16| match symbols.name(address) {
+            Some(name) => name.to_string(),
+            None if address == self.start_address => "start".to_string(),
+            None => format!("sub_{:03x}", address),
+        };
+        // each frame's subroutine holds the call of the frame above it
+        let mut functions = vec![self.start_address];
+        functions.extend(self.stack.iter().map(|frame| frame.subroutine));
+        let mut locations = vec![self.pc];
+        locations.extend(self.stack.iter().rev().map(|frame| frame.call));
+        locations.iter().zip(functions.iter().rev()).enumerate()
+            .map(|(depth, (&location, &start))| {
+                if location > start {
+                    format!("#{} {:#05x} in {}+{:#x}", depth, location, function(start), location - start)
+                } else {
+                    format!("#{} {:#05x} in {}", depth, location, function(start))
+                }
+            })
+            .collect()
+    }
+
+    fn stack_full(&self) -> bool {
+        match self.stack_depth {
+            StackDepth::Frames(depth) => self.stack.len() >= depth,
+            StackDepth::Unlimited => false,
+        }
+    }
+
+    pub fn peek(&self, address: u16) -> Option<u8> {
+        ((address as usize) < self.bus.size()).then(|| self.bus.read_byte(address))
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) -> Result<(), String> {
+        if address as usize >= self.bus.size() {
+            return Err(format!("{:#06x} is outside of memory", address));
+        }
+        self.bus.write_byte(address, value);
+        Ok(())
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    pub fn vram(&self) -> &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT] {
+        &self.vram
+    }
+
+    // The timers count down at 60 Hz regardless of the instruction rate, so the
+    // frontend calls this once per frame
+    pub fn tick_timers(&mut self) {
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn audio_pattern(&self) -> Option<AudioPattern> {
+        self.audio_pattern.map(|bits| AudioPattern { bits, pitch: self.pitch })
+    }
+
+    pub fn tick(&mut self, keyboard: &[bool; 16]) -> ProcessorOutput<'_> {
+        self.vram_changed = false;
+        self.sound_timer_set = None;
+        self.audio_pattern_changed = false;
+        self.busy_wait = None;
+        // TODO Check keyboard
+
+        // a strict bus doesn't run what it shouldn't, the processor stays put
+        if let Some(violation) = self.bus.check(Access::Execute, self.pc) {
+            self.violation(violation);
+            return self.output();
+        }
+        if self.uninitialized != UninitializedCheck::Off {
+            self.check_initialized(self.pc);
+            self.check_initialized(self.pc.wrapping_add(1));
+        }
         // chip-8 is big endian
         let opcode = self.bus.read_word(self.pc);
+        if self.trace {
+            eprintln!("{:03X}: {:04X}  v: {:02X?} I: {:03X} DT: {} ST: {}",
+                self.pc, opcode, self.v, self.reg_i, self.delay_timer, self.sound_timer);
+        }
         self.execute_opcode(opcode, keyboard)
     }
     
-    fn execute_opcode(&mut self, opcode: u16, keyboard: &[bool; 16]) -> ProcessorOutput {
-        self.disassembler.disassemble(opcode);
+    fn execute_opcode(&mut self, opcode: u16, keyboard: &[bool; 16]) -> ProcessorOutput<'_> {
+        let pc = self.pc;
+        self.hooks.before_instruction(pc, opcode);
+        let (reads, writes) = self.register_use(opcode);
+        if self.uninitialized != UninitializedCheck::Off {
+            for register in 0..=16 {
+                if reads & !self.initialized_registers & (1 << register) != 0 {
+                    self.uninitialized_read(MemoryViolation::UninitializedRegister(register as u8));
+                }
+            }
+        }
+        self.initialized_registers |= reads | writes;
         let nibbles = (
             ((opcode & 0xF000) >> 12) as u8,
             ((opcode & 0x0F00) >> 8) as u8,
@@ -112,39 +439,191 @@ impl Processor {
             (0x8,   _,   _, 0x3) => self.op_xor_vx_vy(vx, vy),
             (0x8,   _,   _, 0x4) => self.op_add_vx_vy(vx, vy),
             (0x8,   _,   _, 0x5) => self.op_sub_vx_vy(vx, vy),
-            (0x8,   _,   _, 0x6) => self.op_shr_vx(vx),
+            (0x8,   _,   _, 0x6) => self.op_shr_vx(vx, vy),
             (0x8,   _,   _, 0x7) => self.op_sub_vx_vy(vy, vx),
-            (0x8,   _,   _, 0xE) => self.op_shl_vx(vx),
+            (0x8,   _,   _, 0xE) => self.op_shl_vx(vx, vy),
             (0x9,   _,   _, 0x0) => self.op_skip_neq_vx_vy(vx, vy),
             (0xA,   _,   _,   _) => self.op_mov_i(addr),
-            (0xB,   _,   _,   _) => todo!(),
+            (0xB,   _,   _,   _) => self.op_jmp_v0(vx, addr),
             (0xC,   _,   _,   _) => self.op_rand(vx, kk),
             (0xD,   _,   _,   _) => self.op_draw(vx, vy, nibbles.3),
             (0xE,   _, 0x9, 0xE) => self.op_skip_key_eq_vx(vx, keyboard),
             (0xE,   _, 0xA, 0x1) => self.op_skip_key_neq_vx(vx, keyboard),
             (0xF,   _, 0x0, 0x7) => self.op_ld_vx_delay(vx),
-            (0xF,   _, 0x0, 0xA) => todo!(),
+            (0xF,   _, 0x0, 0xA) => self.op_wait_key(vx, keyboard),
             (0xF,   _, 0x1, 0x5) => self.op_set_delay(vx),
             (0xF,   _, 0x1, 0x8) => self.op_set_sound(vx),
             (0xF,   _, 0x1, 0xE) => self.op_add_i_vx(vx),
             (0xF,   _, 0x2, 0x9) => self.op_ld_i_mem_vx(vx),
+            (0xF,   _, 0x3, 0x0) => self.op_ld_i_big_font_vx(vx),
             (0xF,   _, 0x3, 0x3) => self.op_bcd(vx),
             (0xF,   _, 0x5, 0x5) => self.op_ld_mem_i_vx(vx),
             (0xF,   _, 0x6, 0x5) => self.op_ld_vx_mem_i(vx),
-            _ => {println!("Invalid opcode: {}", opcode); ProcessorAction::Next},
+            (0xF, 0x0, 0x0, 0x2) => self.op_ld_audio_pattern(),
+            (0xF,   _, 0x3, 0xA) => self.op_set_pitch(vx),
+            _ => {
+                self.hooks.invalid_opcode(pc, opcode);
+                ProcessorAction::Next
+            }
         };
+        self.saved_key_state = *keyboard;
 
         match action {
-            ProcessorAction::Next => self.pc += 2,
-            ProcessorAction::Skip => self.pc += 4,
+            ProcessorAction::Next => self.pc = self.pc.wrapping_add(2),
+            ProcessorAction::Skip => self.pc = self.pc.wrapping_add(4),
             ProcessorAction::Jump(addr) => self.pc = addr,
         }
-        ProcessorOutput { vram: &self.vram, vram_changed: self.vram_changed, beep: {self.sound_timer > 0} }
+        self.hooks.after_instruction(pc, opcode);
+        self.output()
+    }
+
+    fn output(&self) -> ProcessorOutput<'_> {
+        ProcessorOutput {
+            vram: &self.vram,
+            vram_changed: self.vram_changed,
+            beep: self.sound_timer > 0,
+            sound_timer_set: self.sound_timer_set,
+            audio_pattern_changed: self.audio_pattern_changed,
+            busy_wait: self.busy_wait,
+        }
+    }
+
+    // The faults since the last call
+    pub fn take_faults(&mut self) -> Vec<(u16, Fault)> {
+        std::mem::take(&mut self.faults)
+    }
+
+    // Registers an instruction reads and writes as bit masks, V0-VF and I in bit 16
+    fn register_use(&self, opcode: u16) -> (u32, u32) {
+        const V0: u32 = 1;
+        const VF: u32 = 1 << 0xF;
+        const I: u32 = 1 << 16;
+        let x = 1 << ((opcode >> 8) & 0xF);
+        let y = 1 << ((opcode >> 4) & 0xF);
+        // V0 up to and including VX
+        let through_x = (x << 1) - 1;
+        match (opcode >> 12, opcode & 0xF, opcode & 0xFF) {
+            (0x3 | 0x4, _, _) => (x, 0),
+            (0x5 | 0x9, 0x0, _) => (x | y, 0),
+            (0x6, _, _) => (0, x),
+            (0x7, _, _) => (x, x),
+            (0x8, 0x0, _) => (y, x),
+            (0x8, 0x1..=0x3, _) => (x | y, x | if self.quirks.vf_reset { VF } else { 0 }),
+            (0x8, 0x4 | 0x5 | 0x7, _) => (x | y, x | VF),
+            (0x8, 0x6 | 0xE, _) => (if self.quirks.shift_vx { x } else { y }, x | VF),
+            (0xA, _, _) => (0, I),
+            (0xB, _, _) => (if self.quirks.jump_vx { x } else { V0 }, 0),
+            (0xC, _, _) => (0, x),
+            (0xD, _, _) => (x | y | I, VF),
+            (0xE, _, 0x9E | 0xA1) => (x, 0),
+            (0xF, _, 0x07 | 0x0A) => (0, x),
+            (0xF, _, 0x15 | 0x18 | 0x3A) => (x, 0),
+            (0xF, _, 0x1E) => (x | I, I),
+            (0xF, _, 0x29 | 0x30) => (x, I),
+            (0xF, _, 0x33) => (x | I, 0),
+            (0xF, _, 0x55) => (through_x | I, 0),
+            (0xF, _, 0x65) => (I, through_x),
+            (0xF, _, 0x02) => (I, 0),
+            _ => (0, 0),
+        }
+    }
+
+    fn check_initialized(&mut self, address: u16) {
+        if !self.bus.is_initialized(address) && self.reported_addresses.insert(address) {
+            self.uninitialized_read(MemoryViolation::UninitializedRead(address));
+        }
+    }
+
+    fn uninitialized_read(&mut self, violation: MemoryViolation) {
+        match self.uninitialized {
+            UninitializedCheck::Off => (),
+            UninitializedCheck::Warn => eprintln!("warning: {} at PC {:#05x}", violation, self.pc),
+            UninitializedCheck::Break => self.violation(violation),
+        }
+    }
+
+    fn violation(&mut self, violation: MemoryViolation) {
+        self.fault(Fault::Memory(violation));
+    }
+
+    fn fault(&mut self, fault: Fault) {
+        self.hooks.fault(self.pc, fault);
+        self.faults.push((self.pc, fault));
+    }
+}
+
+// Going back to an earlier state needs a copy of the bus
+impl<B: Bus + Clone> Processor<B> {
+    pub fn snapshot(&self) -> Snapshot<B> {
+        Snapshot {
+            pc: self.pc,
+            v: self.v,
+            reg_i: self.reg_i,
+            sound_timer: self.sound_timer,
+            delay_timer: self.delay_timer,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            stack: self.stack.clone(),
+            vram: self.vram,
+            wait_for_key: self.wait_for_key,
+            saved_key_state: self.saved_key_state,
+            rng: self.rng.clone(),
+            bus: self.bus.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot<B>) {
+        self.pc = snapshot.pc;
+        self.v = snapshot.v;
+        self.reg_i = snapshot.reg_i;
+        self.sound_timer = snapshot.sound_timer;
+        self.delay_timer = snapshot.delay_timer;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.stack = snapshot.stack.clone();
+        self.vram = snapshot.vram;
+        self.wait_for_key = snapshot.wait_for_key;
+        self.saved_key_state = snapshot.saved_key_state;
+        self.rng = snapshot.rng.clone();
+        self.bus = snapshot.bus.clone();
+        self.vram_changed = true;
+        self.sound_timer_set = None;
+        self.audio_pattern_changed = false;
+    }
+}
+
+// Memory accesses of instructions, seen by the hooks
+impl<B: Bus> Processor<B> {
+    fn read_byte(&mut self, address: u16) -> u8 {
+        match self.bus.check(Access::Read, address) {
+            Some(violation @ MemoryViolation::OutOfBounds(_)) => {
+                self.violation(violation);
+                return 0;
+            }
+            Some(violation) => self.violation(violation),
+            None => (),
+        }
+        if self.uninitialized != UninitializedCheck::Off {
+            self.check_initialized(address);
+        }
+        let value = self.bus.read_byte(address);
+        self.hooks.memory_read(address, value);
+        value
+    }
+
+    // Refused writes are left out
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if let Some(violation) = self.bus.check(Access::Write, address) {
+            self.violation(violation);
+            return;
+        }
+        self.bus.write_byte(address, value);
+        self.hooks.memory_write(address, value);
     }
 }
 
 // Opcodes
-impl Processor {
+impl<B: Bus> Processor<B> {
     fn op_nop(&self) -> ProcessorAction {
         ProcessorAction::Next
     }
@@ -159,22 +638,47 @@ impl Processor {
         ProcessorAction::Next
     }
 
+    // A RET without a call or a CALL past the stack depth is trapped and the
+    // processor stays on it
     fn op_ret(&mut self) -> ProcessorAction {
-        // pop address from stack
-        self.pc = self.stack[self.sp];
-        // dec stack pointer
-        self.sp -= 1;
-        ProcessorAction::Next
+        match self.stack.pop() {
+            Some(frame) => ProcessorAction::Jump(frame.return_address()),
+            None => {
+                self.fault(Fault::StackUnderflow);
+                ProcessorAction::Jump(self.pc)
+            }
+        }
     }
 
-    fn op_jmp(&self, addr: u16) -> ProcessorAction {
+    fn op_jmp(&mut self, addr: u16) -> ProcessorAction {
+        if addr == self.pc {
+            self.busy_wait = Some(BusyWait::Halted);
+        } else if self.polls_delay_timer(addr) {
+            self.busy_wait = Some(BusyWait::DelayTimer);
+        }
         ProcessorAction::Jump(addr)
     }
 
+    // A jump back to
+    //   FX07  LD VX, DT
+    //   3XNN  SE VX, NN (or 4XNN SNE)
+    //   1NNN  JP to the FX07
+    // runs the same way until the delay timer changes
+    fn polls_delay_timer(&self, addr: u16) -> bool {
+        if addr.wrapping_add(4) != self.pc {
+            return false;
+        }
+        let load = self.bus.read_word(addr);
+        let skip = self.bus.read_word(addr.wrapping_add(2));
+        load & 0xF0FF == 0xF007 && matches!(skip >> 12, 0x3 | 0x4) && (skip >> 8) & 0xF == (load >> 8) & 0xF
+    }
+
     fn op_call(&mut self, addr: u16) -> ProcessorAction {
-        // inc stack_pointer
-        self.sp += 1;
-        self.stack[self.sp] = self.pc;
+        if self.stack_full() {
+            self.fault(Fault::StackOverflow { subroutine: addr, depth: self.stack.len() });
+            return ProcessorAction::Jump(self.pc);
+        }
+        self.stack.push(StackFrame { call: self.pc, subroutine: addr });
         ProcessorAction::Jump(addr)
     }
 
@@ -206,19 +710,29 @@ impl Processor {
 
     fn op_or_vx_vy(&mut self, vx: usize, vy: usize) -> ProcessorAction {
         self.v[vx].bitor_assign(self.v[vy]);
+        self.reset_vf();
         ProcessorAction::Next
     }
 
     fn op_and_vx_vy(&mut self, vx: usize, vy: usize) -> ProcessorAction {
         self.v[vx].bitand_assign(self.v[vy]);
+        self.reset_vf();
         ProcessorAction::Next
     }
 
     fn op_xor_vx_vy(&mut self, vx: usize, vy: usize) -> ProcessorAction {
         self.v[vx].bitxor_assign(self.v[vy]);
+        self.reset_vf();
         ProcessorAction::Next
     }
 
+    // The VIP's logic instructions leave VF clobbered with 0
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xf_usize] = 0;
+        }
+    }
+
     fn op_add_vx_vy(&mut self, vx: usize, vy: usize) -> ProcessorAction {
         let (res, carry) = self.v[vx].overflowing_add(self.v[vy]);
         self.v[vx] = res;
@@ -243,15 +757,15 @@ impl Processor {
         ProcessorAction::Next
     }
 
-    fn op_shr_vx(&mut self, vx: usize) -> ProcessorAction {
-        let x = self.v[vx];
+    fn op_shr_vx(&mut self, vx: usize, vy: usize) -> ProcessorAction {
+        let x = if self.quirks.shift_vx { self.v[vx] } else { self.v[vy] };
         self.v[0xf_usize] = x.bitand(0x1);
         self.v[vx] = x.shr(1);
         ProcessorAction::Next
     }
 
-    fn op_shl_vx(&mut self, vx: usize) -> ProcessorAction {
-        let x = self.v[vx];
+    fn op_shl_vx(&mut self, vx: usize, vy: usize) -> ProcessorAction {
+        let x = if self.quirks.shift_vx { self.v[vx] } else { self.v[vy] };
         self.v[0xf_usize] = x.bitand(0x80) >> 7; // Check msb
         self.v[vx] = x.shl(1);
         ProcessorAction::Next
@@ -269,22 +783,38 @@ impl Processor {
         ProcessorAction::Next
     }
 
+    fn op_jmp_v0(&self, vx: usize, addr: u16) -> ProcessorAction {
+        let offset = if self.quirks.jump_vx { self.v[vx] } else { self.v[0] };
+        ProcessorAction::Jump((addr + offset as u16) & 0x0FFF)
+    }
+
     fn op_rand(&mut self, vx: usize, kk: u8) -> ProcessorAction {
-        let mut rng = rand::thread_rng();
-        let random: u8 = rng.gen();
+        let random: u8 = self.rng.gen();
         self.v[vx] = random.bitand(kk);
         ProcessorAction::Next
     }
 
     fn op_draw(&mut self, vx: usize, vy: usize, n: u8) -> ProcessorAction {
         self.v[0xf_usize] = 0;
+        // the starting position always wraps, the rest of the sprite is clipped
+        // or wraps around depending on the platform
+        let start_x = self.v[vx] as usize % CHIP_8_WIDTH;
+        let start_y = self.v[vy] as usize % CHIP_8_HEIGHT;
 
         for line in 0..n {
-            let y = ((self.v[vy] + line) as usize) % CHIP_8_HEIGHT;
-            let sprite = self.bus.read_byte(self.reg_i + (line as u16));
+            let y = start_y + line as usize;
+            if y >= CHIP_8_HEIGHT && self.quirks.clip_sprites {
+                break;
+            }
+            let y = y % CHIP_8_HEIGHT;
+            let sprite = self.read_byte(self.reg_i.wrapping_add(line as u16));
 
             for bit in 0..8_u8{
-                let x = ((self.v[vx] + bit) as usize) % CHIP_8_WIDTH;
+                let x = start_x + bit as usize;
+                if x >= CHIP_8_WIDTH && self.quirks.clip_sprites {
+                    break;
+                }
+                let x = x % CHIP_8_WIDTH;
                 // check for collision
                 if sprite.shr(7-bit).bitand(1) == 1 {
                     if self.vram[y][x] == 0xFF {
@@ -296,6 +826,7 @@ impl Processor {
         }
         
         self.vram_changed = true;
+        self.hooks.draw(start_x as u8, start_y as u8, n, self.v[0xF] == 1);
         ProcessorAction::Next
     }
 
@@ -322,11 +853,48 @@ impl Processor {
 
     fn op_set_delay(&mut self, vx: usize) -> ProcessorAction {
         self.delay_timer = self.v[vx];
+        self.hooks.timer_write(Timer::Delay, self.v[vx]);
         ProcessorAction::Next
     }
 
     fn op_set_sound(&mut self, vx: usize) -> ProcessorAction {
         self.sound_timer = self.v[vx];
+        self.sound_timer_set = Some(self.v[vx]);
+        self.hooks.timer_write(Timer::Sound, self.v[vx]);
+        ProcessorAction::Next
+    }
+
+    // Stays on FX0A until a key goes down that was up before, that key goes into VX
+    fn op_wait_key(&mut self, vx: usize, keyboard: &[bool; 16]) -> ProcessorAction {
+        if !self.wait_for_key {
+            self.wait_for_key = true;
+            self.hooks.key_wait(vx);
+            return ProcessorAction::Jump(self.pc);
+        }
+        let pressed = (0..16).find(|&key| keyboard[key] && !self.saved_key_state[key]);
+        match pressed {
+            Some(key) => {
+                self.v[vx] = key as u8;
+                self.wait_for_key = false;
+                ProcessorAction::Next
+            }
+            None => ProcessorAction::Jump(self.pc),
+        }
+    }
+
+    fn op_ld_audio_pattern(&mut self) -> ProcessorAction {
+        let mut pattern = [0; 16];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_byte(self.reg_i.wrapping_add(offset as u16));
+        }
+        self.audio_pattern = Some(pattern);
+        self.audio_pattern_changed = true;
+        ProcessorAction::Next
+    }
+
+    fn op_set_pitch(&mut self, vx: usize) -> ProcessorAction {
+        self.pitch = self.v[vx];
+        self.audio_pattern_changed = true;
         ProcessorAction::Next
     }
 
@@ -337,38 +905,51 @@ impl Processor {
 
     fn op_bcd(&mut self, vx: usize) -> ProcessorAction {
         let val = self.v[vx];
-        self.bus.write(self.reg_i+2, (val % 100) % 10);
-        self.bus.write(self.reg_i+1, (val / 10) % 10);
-        self.bus.write(self.reg_i, (val / 100));
+        self.write_byte(self.reg_i.wrapping_add(2), (val % 100) % 10);
+        self.write_byte(self.reg_i.wrapping_add(1), (val / 10) % 10);
+        self.write_byte(self.reg_i, (val / 100));
 
         ProcessorAction::Next
     }
 
     fn op_ld_i_mem_vx(&mut self, vx: usize) -> ProcessorAction {
-        self.reg_i = (self.v[vx] as u16 * 5u16) & 0x0FFF;
+        self.reg_i = small_glyph(self.font_address, self.v[vx]);
+        ProcessorAction::Next
+    }
+
+    fn op_ld_i_big_font_vx(&mut self, vx: usize) -> ProcessorAction {
+        self.reg_i = big_glyph(self.font_address, self.v[vx]);
         ProcessorAction::Next
     }
 
     fn op_ld_mem_i_vx(&mut self, vx: usize) -> ProcessorAction {
-        let val = self.v[vx];
-        self.bus.write(self.reg_i, val);
+        for x in 0..=vx {
+            self.write_byte(self.reg_i.wrapping_add(x as u16), self.v[x]);
+        }
+        if self.quirks.memory_increment {
+            self.reg_i = self.reg_i.wrapping_add(vx as u16 + 1);
+        }
         ProcessorAction::Next
     }
 
     fn op_ld_vx_mem_i(&mut self, vx: usize) -> ProcessorAction {
-        self.v[vx] = self.bus.read_byte(self.reg_i);
+        for x in 0..=vx {
+            self.v[x] = self.read_byte(self.reg_i.wrapping_add(x as u16));
+        }
+        if self.quirks.memory_increment {
+            self.reg_i = self.reg_i.wrapping_add(vx as u16 + 1);
+        }
         ProcessorAction::Next
     }
 }
 
 #[cfg(test)]
-
-use crate::disassembler::NullDisassembler;
+use crate::hooks::NoHooks;
 
 #[test]
 fn test_op_shr() {
     let bus = MemoryBus::new();
-    let mut cpu = Processor::new(bus, Box::new(NullDisassembler{}));
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
     let keyboard = [false; 16];
 
     cpu.v[0] = 5;
@@ -380,7 +961,7 @@ fn test_op_shr() {
 #[test]
 fn test_op_shl() {
     let bus = MemoryBus::new();
-    let mut cpu = Processor::new(bus, Box::new(NullDisassembler{}));
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
     let keyboard = [false; 16];
 
     cpu.v[0] = 255;
@@ -392,7 +973,7 @@ fn test_op_shl() {
 #[test]
 fn test_op_or() {
     let bus = MemoryBus::new();
-    let mut cpu = Processor::new(bus, Box::new(NullDisassembler{}));
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
     let keyboard = [false; 16];
 
     cpu.v[0] = 255;
@@ -404,11 +985,251 @@ fn test_op_or() {
 #[test]
 fn test_op_and() {
     let bus = MemoryBus::new();
-    let mut cpu = Processor::new(bus, Box::new(NullDisassembler{}));
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
     let keyboard = [false; 16];
 
     cpu.v[0] = 255;
     cpu.v[1] = 0;
     cpu.execute_opcode(0x8012, &keyboard);
     assert_eq!(cpu.v[0], 0);
-}
\ No newline at end of file
+}
+#[test]
+fn test_timers_tick_per_frame() {
+    let bus = MemoryBus::new();
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
+    let keyboard = [false; 16];
+
+    cpu.v[0] = 2;
+    let output = cpu.execute_opcode(0xF018, &keyboard);
+    assert_eq!(output.sound_timer_set, Some(2));
+    assert!(output.beep);
+    cpu.execute_opcode(0xF015, &keyboard);
+    assert_eq!(cpu.delay_timer, 2);
+
+    cpu.tick_timers();
+    cpu.tick_timers();
+    assert_eq!(cpu.sound_timer, 0);
+    assert_eq!(cpu.delay_timer, 0);
+    cpu.tick_timers();
+    assert_eq!(cpu.delay_timer, 0);
+}
+
+#[test]
+fn test_op_audio_pattern() {
+    let mut bus = MemoryBus::new();
+    for offset in 0..16 {
+        bus.write_byte(0x300 + offset, offset as u8);
+    }
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
+    let keyboard = [false; 16];
+    assert!(cpu.audio_pattern().is_none());
+
+    cpu.reg_i = 0x300;
+    let output = cpu.execute_opcode(0xF002, &keyboard);
+    assert!(output.audio_pattern_changed);
+    cpu.v[3] = 112;
+    cpu.execute_opcode(0xF33A, &keyboard);
+
+    let pattern = cpu.audio_pattern().unwrap();
+    assert_eq!(pattern.bits[15], 15);
+    assert_eq!(pattern.pitch, 112);
+}
+
+#[test]
+fn test_reset() {
+    let mut bus = MemoryBus::new();
+    bus.write_byte(0x300, 0xAB);
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
+    let keyboard = [false; 16];
+
+    cpu.execute_opcode(0x6105, &keyboard);
+    cpu.execute_opcode(0xA300, &keyboard);
+    cpu.execute_opcode(0xF115, &keyboard);
+    cpu.execute_opcode(0xD005, &keyboard);
+    cpu.reset();
+    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(cpu.v[1], 0);
+    assert_eq!(cpu.reg_i, 0);
+    assert_eq!(cpu.delay_timer, 0);
+    assert!(cpu.vram.iter().all(|row| row.iter().all(|&pixel| pixel == 0)));
+    assert_eq!(cpu.bus.read_byte(0x300), 0xAB);
+}
+
+#[test]
+fn test_quirks() {
+    use crate::platform::Platform;
+    let keyboard = [false; 16];
+
+    let mut vip = Processor::new(MemoryBus::new(), Box::new(NoHooks));
+    let mut schip = Processor::new(MemoryBus::new(), Box::new(NoHooks))
+        .with_quirks(Platform::SuperChip.quirks());
+    for cpu in [&mut vip, &mut schip] {
+        cpu.v[0] = 0x10;
+        cpu.v[1] = 0x03;
+        cpu.v[2] = 0x08;
+        cpu.v[0xf] = 1;
+        cpu.reg_i = 0x300;
+    }
+
+    // 8XY6 shifts VY on the VIP, VX on the SUPER-CHIP
+    vip.execute_opcode(0x8216, &keyboard);
+    schip.execute_opcode(0x8216, &keyboard);
+    assert_eq!(vip.v[2], 0x01);
+    assert_eq!(schip.v[2], 0x04);
+
+    // BNNN adds V0, BXNN adds VX
+    vip.execute_opcode(0xB120, &keyboard);
+    schip.execute_opcode(0xB120, &keyboard);
+    assert_eq!(vip.pc, 0x130);
+    assert_eq!(schip.pc, 0x123);
+
+    // FX55 stores V0 to VX and moves I past them on the VIP only
+    vip.execute_opcode(0xF155, &keyboard);
+    schip.execute_opcode(0xF155, &keyboard);
+    assert_eq!(vip.reg_i, 0x302);
+    assert_eq!(schip.reg_i, 0x300);
+    assert_eq!(vip.bus.read_byte(0x300), 0x10);
+    assert_eq!(vip.bus.read_byte(0x301), 0x03);
+
+    vip.execute_opcode(0x6F01, &keyboard);
+    vip.execute_opcode(0x8011, &keyboard);
+    assert_eq!(vip.v[0xf], 0);
+}
+
+#[test]
+fn test_op_ld_vx_mem_i() {
+    let mut bus = MemoryBus::new();
+    for offset in 0..4 {
+        bus.write_byte(0x300 + offset, offset as u8 + 1);
+    }
+    let mut cpu = Processor::new(bus, Box::new(NoHooks));
+    cpu.reg_i = 0x300;
+    cpu.execute_opcode(0xF265, &[false; 16]);
+    assert_eq!(cpu.v[..4], [1, 2, 3, 0]);
+}
+
+#[test]
+fn test_draw_clips_or_wraps() {
+    use crate::platform::Platform;
+    let keyboard = [false; 16];
+
+    let mut bus = MemoryBus::new();
+    bus.write_byte(0x300, 0xFF);
+    let mut clipped = Processor::new(bus, Box::new(NoHooks));
+    clipped.v[0] = 60;
+    clipped.reg_i = 0x300;
+    clipped.execute_opcode(0xD011, &keyboard);
+    assert_eq!(clipped.vram[0][63], 0xFF);
+    assert_eq!(clipped.vram[0][0], 0);
+
+    let mut bus = MemoryBus::new();
+    bus.write_byte(0x300, 0xFF);
+    let mut wrapped = Processor::new(bus, Box::new(NoHooks))
+        .with_quirks(Platform::XoChip.quirks());
+    wrapped.v[0] = 60;
+    wrapped.reg_i = 0x300;
+    wrapped.execute_opcode(0xD011, &keyboard);
+    assert_eq!(wrapped.vram[0][3], 0xFF);
+}
+
+#[test]
+fn test_seeded_rand() {
+    let keyboard = [false; 16];
+    let mut a = Processor::new(MemoryBus::new(), Box::new(NoHooks)).with_seed(7);
+    let mut b = Processor::new(MemoryBus::new(), Box::new(NoHooks)).with_seed(7);
+    for _ in 0..8 {
+        a.execute_opcode(0xC0FF, &keyboard);
+        b.execute_opcode(0xC0FF, &keyboard);
+        assert_eq!(a.v[0], b.v[0]);
+    }
+}
+
+#[test]
+fn test_font_address() {
+    let keyboard = [false; 16];
+    let mut cpu = Processor::new(MemoryBus::new(), Box::new(NoHooks)).with_font_address(0x50);
+    cpu.v[3] = 0xA;
+    cpu.execute_opcode(0xF329, &keyboard);
+    assert_eq!(cpu.reg_i, 0x50 + 0xA * 5);
+    cpu.execute_opcode(0xF330, &keyboard);
+    assert_eq!(cpu.reg_i, 0x50 + 80 + 0xA * 10);
+}
+
+#[test]
+fn test_state_and_mutators() {
+    let mut cpu = Processor::new(MemoryBus::new(), Box::new(NoHooks));
+    cpu.set_register(0xA, 42).unwrap();
+    assert!(cpu.set_register(16, 1).is_err());
+    cpu.set_i(0x300);
+    cpu.set_pc(0x400).unwrap();
+    assert!(cpu.set_pc(0x1000).is_err());
+    cpu.set_timers(3, 4);
+    cpu.push_stack(0x202).unwrap();
+    cpu.push_stack(0x306).unwrap();
+    cpu.poke(0x300, 0x99).unwrap();
+    assert!(cpu.poke(0x1000, 0).is_err());
+
+    let state = cpu.state();
+    assert_eq!(state.v[0xA], 42);
+    assert_eq!((state.pc, state.i, state.delay_timer, state.sound_timer), (0x400, 0x300, 3, 4));
+    assert_eq!(state.stack, vec![0x202, 0x306]);
+    assert_eq!(cpu.peek(0x300), Some(0x99));
+    assert_eq!(cpu.peek(0x1000), None);
+
+    assert_eq!(cpu.pop_stack(), Some(0x306));
+    assert_eq!(cpu.pop_stack(), Some(0x202));
+    assert_eq!(cpu.pop_stack(), None);
+    // 00EE returns to what was pushed
+    cpu.push_stack(0x208).unwrap();
+    cpu.execute_opcode(0x00EE, &[false; 16]);
+    assert_eq!(cpu.state().pc, 0x20A);
+}
+
+#[test]
+fn test_custom_bus() {
+    // 256 bytes of memory that count the writes
+    struct SmallBus {
+        memory: [u8; 256],
+        writes: usize,
+    }
+    impl Bus for SmallBus {
+        fn read_byte(&self, address: u16) -> u8 {
+            self.memory[address as usize]
+        }
+        fn write_byte(&mut self, address: u16, value: u8) {
+            self.memory[address as usize] = value;
+            self.writes += 1;
+        }
+        fn size(&self) -> usize {
+            self.memory.len()
+        }
+    }
+
+    // LD V0, 0x42; LD I, 0x80; LD [I], V0
+    let mut bus = SmallBus { memory: [0; 256], writes: 0 };
+    bus.memory[..6].copy_from_slice(&[0x60, 0x42, 0xA0, 0x80, 0xF0, 0x55]);
+    let mut cpu = Processor::new(bus, Box::new(NoHooks)).with_start_address(0);
+    for _ in 0..3 {
+        cpu.tick(&[false; 16]);
+    }
+    assert_eq!(cpu.bus.memory[0x80], 0x42);
+    assert_eq!(cpu.bus.writes, 1);
+    assert!(cpu.set_pc(0x100).is_err());
+}
+
+#[test]
+fn test_pc_wraps_around() {
+    let mut cpu = Processor::new(MemoryBus::with_size(0x10000), Box::new(NoHooks));
+    let keyboard = [false; 16];
+    // LD V0, 0 at the very end of 64K
+    cpu.poke(0xFFFE, 0x60).unwrap();
+    cpu.set_pc(0xFFFE).unwrap();
+    cpu.tick(&keyboard);
+    assert_eq!(cpu.state().pc, 0x0000);
+
+    // SE V0, 0 skips past the end as well
+    cpu.poke(0xFFFE, 0x30).unwrap();
+    cpu.set_pc(0xFFFE).unwrap();
+    cpu.tick(&keyboard);
+    assert_eq!(cpu.state().pc, 0x0002);
+}
@@ -0,0 +1,142 @@
+// Frames run per 60 Hz frame while fast-forwarding
+const FAST_FORWARD_FRAMES: u32 = 4;
+// In slow motion one frame is run every this many 60 Hz frames
+const SLOW_MOTION_DIVIDER: u64 = 4;
+// Instructions per frame the speed hotkeys step through
+const SPEED_STEPS: [u32; 14] = [1, 2, 3, 5, 7, 10, 15, 20, 30, 50, 100, 200, 500, 1000];
+
+// How the emulation runs: paused, slowed down, fast-forwarded and at which CPU speed
+pub struct RunControl {
+    pub paused: bool,
+    pub slow_motion: bool,
+    // set while the fast-forward key is held
+    pub fast_forward: bool,
+    pub instructions_per_frame: u32,
+    // a single frame was requested while paused
+    advance: bool,
+    frame: u64,
+}
+
+impl RunControl {
+    pub fn new(instructions_per_frame: u32) -> Self {
+        RunControl {
+            paused: false,
+            slow_motion: false,
+            fast_forward: false,
+            instructions_per_frame,
+            advance: false,
+            frame: 0,
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+    }
+
+    // Runs a single frame when paused, pauses otherwise
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.advance = true;
+        }
+        self.paused = true;
+    }
+
+    pub fn speed_up(&mut self) {
+        if let Some(&step) = SPEED_STEPS.iter().find(|&&step| step > self.instructions_per_frame) {
+            self.instructions_per_frame = step;
+        }
+    }
+
+    pub fn speed_down(&mut self) {
+        if let Some(&step) = SPEED_STEPS.iter().rev().find(|&&step| step < self.instructions_per_frame) {
+            self.instructions_per_frame = step;
+        }
+    }
+
+    // Number of emulated frames to run in this 60 Hz frame
+    pub fn frames_to_run(&mut self) -> u32 {
+        self.frame += 1;
+        if self.paused {
+            let frames = self.advance as u32;
+            self.advance = false;
+            frames
+        } else if self.fast_forward {
+            FAST_FORWARD_FRAMES
+        } else if self.slow_motion {
+            self.frame.is_multiple_of(SLOW_MOTION_DIVIDER) as u32
+        } else {
+            1
+        }
+    }
+
+    // Window title showing the current state, e.g. "chip-8-rs - 10 ipf - paused"
+    pub fn title(&self) -> String {
+        let mut title = format!("chip-8-rs - {} ipf", self.instructions_per_frame);
+        if self.paused {
+            title.push_str(" - paused");
+        } else if self.fast_forward {
+            title.push_str(" - fast-forward");
+        } else if self.slow_motion {
+            title.push_str(" - slow motion");
+        }
+        title
+    }
+}
+
+#[test]
+fn test_frames_to_run() {
+    let mut control = RunControl::new(10);
+    assert_eq!(control.frames_to_run(), 1);
+
+    control.fast_forward = true;
+    assert_eq!(control.frames_to_run(), FAST_FORWARD_FRAMES);
+    control.fast_forward = false;
+
+    control.toggle_slow_motion();
+    let frames: u32 = (0..SLOW_MOTION_DIVIDER * 3).map(|_| control.frames_to_run()).sum();
+    assert_eq!(frames, 3);
+    control.toggle_slow_motion();
+
+    control.toggle_pause();
+    assert_eq!(control.frames_to_run(), 0);
+    control.advance_frame();
+    assert_eq!(control.frames_to_run(), 1);
+    assert_eq!(control.frames_to_run(), 0);
+    control.toggle_pause();
+    assert_eq!(control.frames_to_run(), 1);
+
+    // advancing while running pauses first
+    control.advance_frame();
+    assert_eq!(control.frames_to_run(), 0);
+}
+
+#[test]
+fn test_speed_steps() {
+    let mut control = RunControl::new(10);
+    control.speed_up();
+    assert_eq!(control.instructions_per_frame, 15);
+    control.speed_down();
+    control.speed_down();
+    assert_eq!(control.instructions_per_frame, 7);
+
+    // speeds from the command line snap to the next step
+    let mut control = RunControl::new(12);
+    control.speed_down();
+    assert_eq!(control.instructions_per_frame, 10);
+
+    let mut control = RunControl::new(1);
+    control.speed_down();
+    assert_eq!(control.instructions_per_frame, 1);
+}
+
+#[test]
+fn test_title() {
+    let mut control = RunControl::new(10);
+    assert_eq!(control.title(), "chip-8-rs - 10 ipf");
+    control.toggle_pause();
+    assert_eq!(control.title(), "chip-8-rs - 10 ipf - paused");
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Names for addresses in a program, read from a symbol file with a label per line.
+// Addresses are hexadecimal like in assembler listings, 0x in front or not.
+//   0x2A0 draw_player
+//   0x2C4 move_ball  # comments are allowed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut names = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (address, name) = line.split_once(char::is_whitespace)
+                .ok_or(format!("line {}: expected <address> <name>", number + 1))?;
+            let digits = address.strip_prefix("0x").or(address.strip_prefix("0X")).unwrap_or(address);
+            let address = u16::from_str_radix(digits, 16)
+                .map_err(|_| format!("line {}: invalid address {}", number + 1, address))?;
+            names.insert(address, name.trim().to_string());
+        }
+        Ok(Symbols { names })
+    }
+
+    pub fn load(path: &Path) -> Result<Symbols, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read symbols {}: {}", path.display(), err))?;
+        Symbols::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+}
+
+// The symbol file given, or the ROM's own if it has one. Without either
+// addresses go unnamed.
+pub fn load_symbols(path: Option<&str>, rom: &str) -> Result<Symbols, String> {
+    if let Some(path) = path {
+        return Symbols::load(Path::new(path));
+    }
+    let rom_symbols = rom_symbols_path(rom);
+    if rom_symbols.is_file() {
+        return Symbols::load(&rom_symbols);
+    }
+    Ok(Symbols::default())
+}
+
+// Per ROM symbols live next to the ROM, e.g. games/pong.sym for games/pong.ch8
+pub fn rom_symbols_path(rom: &str) -> PathBuf {
+    Path::new(rom).with_extension("sym")
+}
+
+#[test]
+fn test_parse_symbols() {
+    let symbols = Symbols::parse("
+        # pong
+        0x200 main
+        2A0   draw_paddle  # left and right
+    ").unwrap();
+    assert_eq!(symbols.name(0x200), Some("main"));
+    assert_eq!(symbols.name(0x2A0), Some("draw_paddle"));
+    assert_eq!(symbols.name(0x2A2), None);
+
+    assert!(Symbols::parse("main").is_err());
+    assert!(Symbols::parse("0xZZZ main").is_err());
+    assert!(Symbols::parse("0x0x200 main").is_err());
+}
+
+#[test]
+fn test_rom_symbols_path() {
+    assert_eq!(rom_symbols_path("games/pong.ch8"), Path::new("games/pong.sym"));
+}
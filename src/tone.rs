@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+// XO-CHIP pitch register value that plays the audio pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+const PATTERN_BITS: f32 = 128.0;
+
+// Timers count down at 60 Hz, a sound timer value of n beeps for n/60 seconds
+const TIMER_HZ: u32 = 60;
+// Length of the fade in and fade out that keep beeps from clicking
+const RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "noise" => Some(Waveform::Noise),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToneSettings {
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        ToneSettings { frequency: DEFAULT_FREQUENCY, volume: DEFAULT_VOLUME, waveform: Waveform::default() }
+    }
+}
+
+// XO-CHIP 1-bit audio: 128 samples played in a loop at a rate set by the pitch
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AudioPattern {
+    pub bits: [u8; 16],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // Bits played per second
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        (self.bits[index / 8] >> (7 - index % 8)) & 1 == 1
+    }
+}
+
+// Generates the buzzer sound sample by sample from the sound timer
+pub struct ToneGenerator {
+    settings: ToneSettings,
+    sample_rate: u32,
+    phase: f32,
+    // envelope between 0.0 (silent) and 1.0 (full volume)
+    gain: f32,
+    ramp_step: f32,
+    // samples left until the sound timer reaches zero
+    remaining: u32,
+    noise: u32,
+    pattern: Option<AudioPattern>,
+    // position inside the pattern in bits, kept across pattern changes so a new
+    // pattern or pitch continues where the last one left off
+    pattern_phase: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> Self {
+        ToneGenerator {
+            settings,
+            sample_rate,
+            phase: 0.0,
+            gain: 0.0,
+            ramp_step: 1.0 / (RAMP_SECONDS * sample_rate as f32),
+            remaining: 0,
+            noise: 0x1234_5678,
+            pattern: None,
+            pattern_phase: 0.0,
+        }
+    }
+
+    // Called when the program loads the sound timer
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.remaining = value as u32 * self.sample_rate / TIMER_HZ;
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.pattern = pattern;
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            let target = if self.remaining > 0 { 1.0 } else { 0.0 };
+            if self.gain < target {
+                self.gain = (self.gain + self.ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - self.ramp_step).max(target);
+            }
+            self.remaining = self.remaining.saturating_sub(1);
+
+            let wave = match self.pattern {
+                Some(pattern) => self.pattern_sample(pattern),
+                None => self.oscillator(),
+            };
+            *sample = if self.gain > 0.0 {
+                self.settings.volume * self.gain * wave
+            } else {
+                0.0
+            };
+            self.phase = (self.phase + self.settings.frequency / self.sample_rate as f32) % 1.0;
+        }
+    }
+
+    fn pattern_sample(&mut self, pattern: AudioPattern) -> f32 {
+        let value = if pattern.bit(self.pattern_phase as usize) { 1.0 } else { -1.0 };
+        self.pattern_phase = (self.pattern_phase + pattern.playback_rate() / self.sample_rate as f32) % PATTERN_BITS;
+        value
+    }
+
+    fn oscillator(&mut self) -> f32 {
+        match self.settings.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Noise => {
+                // xorshift, good enough for a hiss
+                self.noise ^= self.noise << 13;
+                self.noise ^= self.noise >> 17;
+                self.noise ^= self.noise << 5;
+                (self.noise as f32 / u32::MAX as f32) * 2.0 - 1.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+const TEST_RATE: u32 = 48000;
+
+#[test]
+fn test_silent_without_sound_timer() {
+    let mut tone = ToneGenerator::new(ToneSettings::default(), TEST_RATE);
+    let mut out = [1.0; 256];
+    tone.fill(&mut out);
+    assert!(out.iter().all(|&s| s == 0.0));
+    assert!(tone.remaining == 0 && tone.gain == 0.0);
+}
+
+#[test]
+fn test_beep_length() {
+    let mut tone = ToneGenerator::new(ToneSettings::default(), TEST_RATE);
+    tone.set_sound_timer(3);
+    // 3/60 of a second, then the release ramp
+    let ramp = (RAMP_SECONDS * TEST_RATE as f32) as usize;
+    let mut out = vec![0.0; (TEST_RATE / 20) as usize + ramp + 1];
+    tone.fill(&mut out);
+    assert!(out[(TEST_RATE / 40) as usize] != 0.0);
+    assert_eq!(*out.last().unwrap(), 0.0);
+    assert!(tone.remaining == 0 && tone.gain == 0.0);
+}
+
+#[test]
+fn test_attack_has_no_click() {
+    let settings = ToneSettings { volume: 1.0, ..ToneSettings::default() };
+    let mut tone = ToneGenerator::new(settings, TEST_RATE);
+    tone.set_sound_timer(10);
+    let mut out = [0.0; 64];
+    tone.fill(&mut out);
+    // the square wave starts at full amplitude, the envelope must fade it in
+    assert!(out[0].abs() < 0.01);
+    let step = tone.ramp_step;
+    assert!(out.iter().enumerate().all(|(i, sample)| sample.abs() <= (i + 1) as f32 * step + f32::EPSILON));
+}
+
+#[test]
+fn test_pattern_playback_rate() {
+    let mut pattern = AudioPattern { bits: [0; 16], pitch: DEFAULT_PITCH };
+    assert_eq!(pattern.playback_rate(), 4000.0);
+    pattern.pitch = 112;
+    assert_eq!(pattern.playback_rate(), 8000.0);
+    pattern.pitch = 16;
+    assert_eq!(pattern.playback_rate(), 2000.0);
+}
+
+#[test]
+fn test_pattern_playback() {
+    let settings = ToneSettings { volume: 1.0, ..ToneSettings::default() };
+    let mut tone = ToneGenerator::new(settings, 8000);
+    // one byte of ones and then zeros, at 4000 bits per second every bit lasts
+    // two samples: 16 samples high, then low
+    let mut bits = [0; 16];
+    bits[0] = 0xFF;
+    tone.set_pattern(Some(AudioPattern { bits, pitch: DEFAULT_PITCH }));
+    tone.gain = 1.0;
+    tone.set_sound_timer(1);
+    let mut out = [0.0; 20];
+    tone.fill(&mut out);
+    assert!(out[..16].iter().all(|&s| s == 1.0));
+    assert!(out[16..].iter().all(|&s| s == -1.0));
+
+    // switching the pitch keeps the position in the pattern
+    let phase = tone.pattern_phase;
+    tone.set_pattern(Some(AudioPattern { bits, pitch: 112 }));
+    assert_eq!(tone.pattern_phase, phase);
+}
+
+#[test]
+fn test_waveform_from_name() {
+    assert_eq!(Waveform::from_name("triangle"), Some(Waveform::Triangle));
+    assert_eq!(Waveform::from_name("sawtooth"), None);
+}
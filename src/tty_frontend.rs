@@ -0,0 +1,207 @@
+use std::io::{self, Read, Write};
+
+use crate::emulator::{fault_message, Emulator};
+use crate::frame_timer::FrameTimer;
+use crate::keymap::Keymap;
+use crate::palette::Palette;
+use crate::{CHIP_8_HEIGHT, CHIP_8_WIDTH};
+
+// Terminals only report key presses, so a key counts as held for this many
+// frames after its last press. Auto repeat keeps it down while held.
+const KEY_HOLD_FRAMES: u32 = 8;
+
+const CTRL_C: u8 = 0x03;
+const ESCAPE: u8 = 0x1B;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TtyStyle {
+    // Two pixels per character cell with upper and lower half blocks
+    HalfBlocks,
+    // 2x4 pixels per character cell with braille dots
+    Braille,
+}
+
+// Puts stdin into non-blocking raw mode and restores it when dropped
+struct RawTerminal {
+    saved: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> io::Result<Self> {
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            libc::cfmakeraw(&mut raw);
+            // return from read immediately, even without input
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawTerminal { saved })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+// Frontend settings that aren't part of the machine
+pub struct TtySettings {
+    pub style: TtyStyle,
+    pub palette: Palette,
+}
+
+pub fn run(emulator: &mut Emulator, keymap: &Keymap, settings: &TtySettings) -> io::Result<()> {
+    let _raw = RawTerminal::enable()?;
+    let mut stdout = io::stdout();
+    // hide the cursor and clear the screen
+    write!(stdout, "\x1b[?25l\x1b[2J")?;
+
+    let result = run_loop(emulator, keymap, settings, &mut stdout);
+
+    write!(stdout, "\x1b[0m\x1b[?25h\r\n")?;
+    stdout.flush()?;
+    result
+}
+
+fn run_loop(
+    emulator: &mut Emulator,
+    keymap: &Keymap,
+    settings: &TtySettings,
+    stdout: &mut io::Stdout,
+) -> io::Result<()> {
+    let mut stdin = io::stdin();
+    let mut hold = [0_u32; 16];
+    let mut keyboard = [false; 16];
+    let mut beeping = false;
+    let mut timer = FrameTimer::new();
+    let mut input = [0_u8; 64];
+    let (on, off) = (settings.palette.on, settings.palette.off);
+    let colors = format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", on.0, on.1, on.2, off.0, off.1, off.2);
+
+    loop {
+        let count = stdin.read(&mut input)?;
+        if is_quit(&input[..count]) {
+            return Ok(());
+        }
+        // keys are named by the character they type, like SDL does for most keys
+        for c in String::from_utf8_lossy(&input[..count]).chars() {
+            if let Some(key) = keymap.key_for(&c.to_string()) {
+                hold[key] = KEY_HOLD_FRAMES;
+            }
+        }
+        for (pressed, frames) in keyboard.iter_mut().zip(hold.iter_mut()) {
+            *pressed = *frames > 0;
+            *frames = frames.saturating_sub(1);
+        }
+
+        let frame = emulator.run_frame(&keyboard);
+        if let Some((pc, fault)) = frame.faults.first() {
+            return Err(io::Error::other(fault_message(*pc, *fault)));
+        }
+        if frame.vram_changed {
+            let picture = match settings.style {
+                TtyStyle::HalfBlocks => render_half_blocks(emulator.framebuffer()),
+                TtyStyle::Braille => render_braille(emulator.framebuffer()),
+            };
+            write!(stdout, "\x1b[H{}{}\x1b[0m", colors, picture)?;
+        }
+        // the terminal bell rings once at the start of every beep
+        if frame.beep && !beeping {
+            write!(stdout, "\x07")?;
+        }
+        beeping = frame.beep;
+        stdout.flush()?;
+
+        timer.wait();
+    }
+}
+
+// Ctrl-C or a lone escape, escape sequences like the arrow keys are ignored
+fn is_quit(input: &[u8]) -> bool {
+    input.contains(&CTRL_C) || input == [ESCAPE]
+}
+
+fn render_half_blocks(vram: &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT]) -> String {
+    let mut out = String::new();
+    for rows in vram.chunks(2) {
+        for x in 0..CHIP_8_WIDTH {
+            let top = rows[0][x] > 0;
+            let bottom = rows.get(1).is_some_and(|row| row[x] > 0);
+            out.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn render_braille(vram: &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT]) -> String {
+    // dot bits of a braille character, indexed by [row][column] inside the cell
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    let mut out = String::new();
+    for rows in vram.chunks(4) {
+        for x in (0..CHIP_8_WIDTH).step_by(2) {
+            let mut bits = 0;
+            for (dy, row) in rows.iter().enumerate() {
+                for dx in 0..2 {
+                    if row[x + dx] > 0 {
+                        bits |= DOTS[dy][dx];
+                    }
+                }
+            }
+            out.push(char::from_u32(0x2800 + bits).unwrap());
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[test]
+fn test_render_half_blocks() {
+    let mut vram = [[0; CHIP_8_WIDTH]; CHIP_8_HEIGHT];
+    vram[0][0] = 0xFF;
+    vram[1][1] = 0xFF;
+    vram[0][2] = 0xFF;
+    vram[1][2] = 0xFF;
+    let picture = render_half_blocks(&vram);
+    let lines: Vec<&str> = picture.split("\r\n").collect();
+    assert_eq!(lines.len(), CHIP_8_HEIGHT / 2 + 1);
+    assert!(lines[0].starts_with("▀▄█ "));
+    assert_eq!(lines[0].chars().count(), CHIP_8_WIDTH);
+}
+
+#[test]
+fn test_render_braille() {
+    let mut vram = [[0; CHIP_8_WIDTH]; CHIP_8_HEIGHT];
+    vram[0][0] = 0xFF;
+    vram[3][1] = 0xFF;
+    let picture = render_braille(&vram);
+    let lines: Vec<&str> = picture.split("\r\n").collect();
+    assert_eq!(lines.len(), CHIP_8_HEIGHT / 4 + 1);
+    assert!(lines[0].starts_with("\u{2881}\u{2800}"));
+    assert_eq!(lines[0].chars().count(), CHIP_8_WIDTH / 2);
+}
+
+#[test]
+fn test_is_quit() {
+    assert!(is_quit(&[CTRL_C]));
+    assert!(is_quit(&[ESCAPE]));
+    // arrow key
+    assert!(!is_quit(&[ESCAPE, b'[', b'A']));
+    assert!(!is_quit(b"q"));
+}
@@ -1,49 +1,123 @@
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use sdl2::video::FullscreenType;
 use sdl2::{Sdl, render::Canvas, video::Window, pixels::Color};
 
-const SCALE_FACTOR: u32 = 15;
-const SCREEN_WIDTH: u32 = 64 * SCALE_FACTOR;
-const SCREEN_HEIGHT: u32 = 32 * SCALE_FACTOR;
-
 use crate::CHIP_8_HEIGHT;
 use crate::CHIP_8_WIDTH;
+use crate::display_filter::{DisplayFilters, Filter, CELL_SIZE};
+use crate::orientation::Orientation;
+use crate::palette::Palette;
+
+const BYTES_PER_PIXEL: usize = 3;
+const TEXTURE_WIDTH: usize = CHIP_8_WIDTH * CELL_SIZE;
+const TEXTURE_HEIGHT: usize = CHIP_8_HEIGHT * CELL_SIZE;
 
 pub struct VideoDriver {
-    canvas: Canvas<Window>
+    canvas: Canvas<Window>,
+    // The framebuffer is uploaded here once per frame and scaled by the GPU
+    texture: Texture,
+    pixels: Vec<u8>,
+    filters: DisplayFilters,
+    orientation: Orientation,
+    palette: Palette,
 }
 
 impl VideoDriver {
-    pub fn new(sdl_context: &Sdl) -> VideoDriver {
+    pub fn new(
+        sdl_context: &Sdl,
+        scale: u32,
+        integer_scale: bool,
+        fullscreen: bool,
+        filters: DisplayFilters,
+        orientation: Orientation,
+        palette: Palette,
+    ) -> VideoDriver {
         let video_subsys = sdl_context.video().unwrap();
-        let window = video_subsys.window("chip-8-rs", SCREEN_WIDTH, SCREEN_HEIGHT)
-            .position_centered()
-            .build()
+        let (logical_width, logical_height) = orientation.output_size(CHIP_8_WIDTH as u32, CHIP_8_HEIGHT as u32);
+        let mut window_builder = video_subsys.window("chip-8-rs", logical_width * scale, logical_height * scale);
+        window_builder.position_centered().resizable();
+        if fullscreen {
+            window_builder.fullscreen_desktop();
+        }
+        let window = window_builder.build().unwrap();
+
+        let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+        // SDL letterboxes the logical resolution into whatever size the window has
+        canvas.set_logical_size(logical_width, logical_height).unwrap();
+        canvas.set_integer_scale(integer_scale).unwrap();
+
+        let texture = canvas.texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, TEXTURE_WIDTH as u32, TEXTURE_HEIGHT as u32)
             .unwrap();
+        let pixels = vec![0; TEXTURE_WIDTH * TEXTURE_HEIGHT * BYTES_PER_PIXEL];
+
+        let mut video_driver = VideoDriver { canvas, texture, pixels, filters, orientation, palette };
+        video_driver.draw(&[[0; CHIP_8_WIDTH]; CHIP_8_HEIGHT]);
+        video_driver
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let mode = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        let _ = window.set_fullscreen(mode);
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
 
-        let mut canvas = window.into_canvas().build().unwrap();
-        canvas.set_draw_color(Color::RGB(0,0,0));
-        canvas.clear();
-        canvas.present();
+    pub fn toggle_filter(&mut self, filter: Filter) {
+        self.filters.toggle(filter);
+    }
 
-        VideoDriver { canvas }
+    // Fading and blending need new frames even when the vram is unchanged
+    pub fn needs_refresh(&self) -> bool {
+        self.filters.is_animating()
     }
 
-    pub fn draw(&mut self, pixels: &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &pixel) in row.iter().enumerate() {
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
-                self.canvas.set_draw_color(get_color(pixel));
-                let _ = self.canvas.fill_rect(Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR));
+    pub fn draw(&mut self, vram: &[[u8; CHIP_8_WIDTH]; CHIP_8_HEIGHT]) {
+        let intensities = self.filters.process(vram);
+        for y in 0..TEXTURE_HEIGHT {
+            for x in 0..TEXTURE_WIDTH {
+                let intensity = intensities[(y / CELL_SIZE) * CHIP_8_WIDTH + x / CELL_SIZE];
+                let shade = self.filters.cell_shade(x % CELL_SIZE, y % CELL_SIZE);
+                let (r, g, b) = self.palette.mix(intensity * shade);
+                let offset = (y * TEXTURE_WIDTH + x) * BYTES_PER_PIXEL;
+                self.pixels[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&[r, g, b]);
             }
         }
-        self.canvas.present();
+        let _ = self.texture.update(None, &self.pixels, TEXTURE_WIDTH * BYTES_PER_PIXEL);
+        self.redraw();
     }
-}
 
-fn get_color(pixel: u8) -> Color{
-    if pixel > 0 {
-        return Color::RGB(0, 225, 0);
+    // Presents the last uploaded frame again, e.g. after the window was resized
+    pub fn redraw(&mut self) {
+        // clear the letterbox bars as well as the picture
+        let (r, g, b) = self.palette.off;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        // the unrotated picture is centred on the logical area and turned around its centre
+        let (logical_width, logical_height) = self.canvas.logical_size();
+        let target = Rect::new(
+            (logical_width as i32 - CHIP_8_WIDTH as i32) / 2,
+            (logical_height as i32 - CHIP_8_HEIGHT as i32) / 2,
+            CHIP_8_WIDTH as u32,
+            CHIP_8_HEIGHT as u32,
+        );
+        let _ = self.canvas.copy_ex(
+            &self.texture,
+            None,
+            target,
+            self.orientation.rotation.degrees() as f64,
+            None,
+            self.orientation.flip_horizontal,
+            self.orientation.flip_vertical,
+        );
+        self.canvas.present();
     }
-    Color::RGB(0, 0, 0)
-}
\ No newline at end of file
+}
@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use crate::audio::AudioSink;
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+// Records mono 16 bit PCM into a WAV file. The sizes in the header are filled in
+// when the writer is finished or dropped.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_size: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<Self> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = BITS_PER_SAMPLE / 8;
+        out.write_all(b"RIFF")?;
+        out.write_all(&0_u32.to_le_bytes())?; // patched in finish
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16_u32.to_le_bytes())?;
+        out.write_all(&1_u16.to_le_bytes())?; // PCM
+        out.write_all(&1_u16.to_le_bytes())?; // mono
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0_u32.to_le_bytes())?; // patched in finish
+        Ok(WavWriter { out, data_size: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * (BITS_PER_SAMPLE / 8) as u32;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn push_samples(&mut self, samples: &[f32]) {
+        if let Err(err) = self.write_samples(samples) {
+            eprintln!("error: writing audio failed: {}", err);
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[test]
+fn test_wav_header() {
+    use std::io::Cursor;
+
+    let mut buffer = Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, 8000).unwrap();
+        writer.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+    }
+    let bytes = buffer.into_inner();
+    assert_eq!(bytes.len(), HEADER_SIZE as usize + 6);
+    assert_eq!(&bytes[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 6);
+    assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 8000);
+    assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+    assert_eq!(i16::from_le_bytes([bytes[46], bytes[47]]), i16::MAX);
+    assert_eq!(i16::from_le_bytes([bytes[48], bytes[49]]), -i16::MAX);
+}